@@ -0,0 +1,230 @@
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+use super::{Color, HdrColor, Scene};
+use math::{Point3, Vec3};
+use shape::MovingInstance;
+use transform::Transform;
+
+
+// values that can be blended, t goes from 0.0 at self to 1.0 at other
+pub trait Interpolate: Copy {
+    fn interpolate(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn interpolate(&self, other: &f64, t: f64) -> f64 {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Vec3 {
+    fn interpolate(&self, other: &Vec3, t: f64) -> Vec3 {
+        *self + (*other - *self) * t
+    }
+}
+
+impl Interpolate for Point3 {
+    fn interpolate(&self, other: &Point3, t: f64) -> Point3 {
+        *self + (*other - *self) * t
+    }
+}
+
+impl Interpolate for Color {
+    fn interpolate(&self, other: &Color, t: f64) -> Color {
+        let channel = |from: u8, to: u8| (from as f64).interpolate(&(to as f64), t).round().clamp(0.0, 255.0) as u8;
+        Color::new(channel(self.r, other.r), channel(self.g, other.g), channel(self.b, other.b))
+    }
+}
+
+impl Interpolate for HdrColor {
+    fn interpolate(&self, other: &HdrColor, t: f64) -> HdrColor {
+        HdrColor::new(self.r.interpolate(&other.r, t), self.g.interpolate(&other.g, t), self.b.interpolate(&other.b, t))
+    }
+}
+
+impl Interpolate for Transform {
+    fn interpolate(&self, other: &Transform, t: f64) -> Transform {
+        Transform::interpolate(self, other, t)
+    }
+}
+
+// how a keyframe moves on to the next one
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Interpolation {
+    Linear,
+    // eases in and out, so the motion starts and stops without a jerk
+    Smooth,
+    // holds the value until the next keyframe
+    Step,
+}
+
+impl Interpolation {
+    fn apply(&self, t: f64) -> f64 {
+        match *self {
+            Interpolation::Linear => t,
+            Interpolation::Smooth => t * t * (3.0 - 2.0 * t),
+            Interpolation::Step => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Keyframe<T> {
+    // in seconds
+    pub time: f64,
+    pub value: T,
+    pub interpolation: Interpolation,
+}
+
+impl<T> Keyframe<T> {
+    pub fn linear(time: f64, value: T) -> Keyframe<T> {
+        Keyframe { time, value, interpolation: Interpolation::Linear }
+    }
+
+    pub fn smooth(time: f64, value: T) -> Keyframe<T> {
+        Keyframe { time, value, interpolation: Interpolation::Smooth }
+    }
+
+    pub fn step(time: f64, value: T) -> Keyframe<T> {
+        Keyframe { time, value, interpolation: Interpolation::Step }
+    }
+}
+
+// value changing over time, it stays at the first and the last keyframe before and after them
+#[derive(Debug, PartialEq, Clone)]
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>,
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new(mut keyframes: Vec<Keyframe<T>>) -> Track<T> {
+        keyframes.retain(|keyframe| !keyframe.time.is_nan());
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        Track { keyframes }
+    }
+
+    pub fn get_keyframes(&self) -> &[Keyframe<T>] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    // None for a track without keyframes
+    pub fn value_at(&self, time: f64) -> Option<T> {
+        let after = self.keyframes.iter().position(|keyframe| keyframe.time > time);
+        match after {
+            None => self.keyframes.last().map(|keyframe| keyframe.value),
+            Some(0) => Some(self.keyframes[0].value),
+            Some(i) => {
+                let start = &self.keyframes[i - 1];
+                let end = &self.keyframes[i];
+                let t = start.interpolation.apply((time - start.time) / (end.time - start.time));
+                Some(start.value.interpolate(&end.value, t))
+            }
+        }
+    }
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Track<T> {
+        Track { keyframes: vec![] }
+    }
+}
+
+// tracks of one of the scene spheres, empty tracks leave the property alone
+#[derive(Debug, Default, Clone)]
+pub struct SphereAnimation {
+    // into the spheres of the scene
+    pub index: usize,
+    pub center: Track<Point3>,
+    pub radius: Track<f64>,
+    pub color: Track<Color>,
+}
+
+// places one of the scene objects, in object space, the object follows it by itself while the shutter is open
+#[derive(Debug, Default, Clone)]
+pub struct ObjectAnimation {
+    // into the objects of the scene
+    pub index: usize,
+    pub transform: Track<Transform>,
+}
+
+// tracks of the camera, the light, the spheres and the objects, empty tracks leave the property alone,
+// moving instances among the objects follow the time by themselves
+#[derive(Debug, Default, Clone)]
+pub struct SceneAnimation {
+    pub eye: Track<Point3>,
+    pub aperture_radius: Track<f64>,
+    pub focus_distance: Track<f64>,
+    pub light_source: Track<Point3>,
+    pub exposure: Track<f64>,
+    pub spheres: Vec<SphereAnimation>,
+    pub objects: Vec<ObjectAnimation>,
+}
+
+// an animation of something the scene doesn't have
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AnimationError {
+    MissingSphere(usize),
+    MissingObject(usize),
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AnimationError::MissingSphere(index) => write!(f, "animated sphere {} isn't in the scene", index),
+            AnimationError::MissingObject(index) => write!(f, "animated object {} isn't in the scene", index),
+        }
+    }
+}
+
+impl Error for AnimationError {}
+
+impl SceneAnimation {
+    // poses the scene at the time and opens the shutter then, keeping how long it stays open,
+    // the scene is left alone when the animation doesn't fit it, animated objects are wrapped
+    // in moving instances, so a scene can be posed only once
+    pub fn apply(&self, scene: &mut Scene, time: f64) -> Result<(), AnimationError> {
+        if let Some(sphere_animation) = self.spheres.iter().find(|animation| animation.index >= scene.spheres.len()) {
+            return Err(AnimationError::MissingSphere(sphere_animation.index));
+        }
+        if let Some(object_animation) = self.objects.iter().find(|animation| animation.index >= scene.objects.len()) {
+            return Err(AnimationError::MissingObject(object_animation.index));
+        }
+        set_from_track(&mut scene.eye, &self.eye, time);
+        set_from_track(&mut scene.camera.aperture_radius, &self.aperture_radius, time);
+        set_from_track(&mut scene.camera.focus_distance, &self.focus_distance, time);
+        set_from_track(&mut scene.light_source, &self.light_source, time);
+        set_from_track(&mut scene.exposure, &self.exposure, time);
+        for sphere_animation in &self.spheres {
+            let sphere = &mut scene.spheres[sphere_animation.index];
+            set_from_track(&mut sphere.center, &sphere_animation.center, time);
+            set_from_track(&mut sphere.radius, &sphere_animation.radius, time);
+            set_from_track(&mut sphere.color, &sphere_animation.color, time);
+        }
+        for object_animation in &self.objects {
+            let object = &mut scene.objects[object_animation.index];
+            *object = Rc::new(MovingInstance::from_track(object.clone(), object_animation.transform.clone()));
+        }
+        let exposure_time = scene.camera.shutter_close - scene.camera.shutter_open;
+        scene.camera.shutter_open = time;
+        scene.camera.shutter_close = time + exposure_time;
+        // the eye blurs along its track while the shutter is open
+        if let (Some(start), Some(end)) = (self.eye.value_at(time), self.eye.value_at(time + exposure_time)) {
+            if exposure_time > 0.0 {
+                scene.camera.eye_motion = (end - start) / exposure_time;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn set_from_track<T: Interpolate>(property: &mut T, track: &Track<T>, time: f64) {
+    if let Some(value) = track.value_at(time) {
+        *property = value;
+    }
+}
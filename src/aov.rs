@@ -0,0 +1,89 @@
+use super::{HdrColor, PixelSample};
+use framebuffer::Layer;
+
+
+// arbitrary output variables, extra images rendered alongside the main one
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Aov {
+    Depth,
+    Normal,
+    Albedo,
+    ObjectId,
+    // 1.0 where the light source is blocked
+    Shadow,
+}
+
+pub const AOV_NAMES: [&str; 5] = ["depth", "normal", "albedo", "object_id", "shadow"];
+
+impl Aov {
+    pub fn from_name(name: &str) -> Option<Aov> {
+        match name {
+            "depth" => Some(Aov::Depth),
+            "normal" => Some(Aov::Normal),
+            "albedo" => Some(Aov::Albedo),
+            "object_id" => Some(Aov::ObjectId),
+            "shadow" => Some(Aov::Shadow),
+            _ => None,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match *self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object_id",
+            Aov::Shadow => "shadow",
+        }
+    }
+
+    // single number aovs go to all channels
+    pub fn get_value(&self, sample: &PixelSample) -> HdrColor {
+        match *self {
+            Aov::Depth => get_gray(sample.depth),
+            Aov::Normal => HdrColor::new(sample.normal.x, sample.normal.y, sample.normal.z),
+            Aov::Albedo => sample.albedo,
+            Aov::ObjectId => get_gray(sample.object_id as f64),
+            Aov::Shadow => get_gray(if sample.in_shadow { 1.0 } else { 0.0 }),
+        }
+    }
+
+    // maps layer values to 0.0..1.0 so they can be saved as regular images
+    pub fn get_display_pixels(&self, layer: &Layer) -> Vec<HdrColor> {
+        let pixels = layer.get_pixels();
+        match *self {
+            Aov::Depth => {
+                let max_depth = pixels.iter()
+                    .map(|p| p.r)
+                    .filter(|depth| depth.is_finite())
+                    .fold(0.0, f64::max);
+                // close is bright, sky is black
+                pixels.iter()
+                    .map(|p| if p.r.is_finite() && max_depth > 0.0 { get_gray(1.0 - p.r / max_depth) } else { get_gray(0.0) })
+                    .collect()
+            }
+            Aov::Normal => pixels.iter()
+                .map(|p| HdrColor::new(p.r * 0.5 + 0.5, p.g * 0.5 + 0.5, p.b * 0.5 + 0.5))
+                .collect(),
+            Aov::ObjectId => pixels.iter().map(|p| get_id_color(p.r as usize)).collect(),
+            Aov::Albedo | Aov::Shadow => pixels.to_vec(),
+        }
+    }
+}
+
+fn get_gray(value: f64) -> HdrColor {
+    HdrColor::new(value, value, value)
+}
+
+// neighbouring ids get easily distinguishable colors
+fn get_id_color(id: usize) -> HdrColor {
+    if id == 0 {
+        return get_gray(0.0);
+    }
+    let hash = (id as u32).wrapping_mul(2_654_435_761);
+    HdrColor::new(
+        ((hash >> 24) & 0xff) as f64 / 255.0,
+        ((hash >> 16) & 0xff) as f64 / 255.0,
+        ((hash >> 8) & 0xff) as f64 / 255.0,
+    )
+}
@@ -0,0 +1,136 @@
+use std::f64::consts::PI;
+use std::io;
+
+use super::HdrColor;
+use framebuffer::Framebuffer;
+use input;
+use math::Vec3;
+
+
+// radiance of rays that don't hit anything, z is up
+pub trait Background {
+    // direction doesn't have to be normalized
+    fn radiance_at(&self, direction: Vec3) -> HdrColor;
+}
+
+pub struct Uniform {
+    pub color: HdrColor,
+}
+
+impl Background for Uniform {
+    fn radiance_at(&self, _direction: Vec3) -> HdrColor {
+        self.color
+    }
+}
+
+// ground below the horizon, blends from the horizon to the zenith above it
+pub struct Gradient {
+    pub zenith_color: HdrColor,
+    pub horizon_color: HdrColor,
+    pub ground_color: HdrColor,
+}
+
+impl Background for Gradient {
+    fn radiance_at(&self, direction: Vec3) -> HdrColor {
+        let elevation = get_elevation(direction);
+        if elevation < 0.0 {
+            return self.ground_color;
+        }
+        mix(self.horizon_color, self.zenith_color, elevation)
+    }
+}
+
+// cheap approximation of a clear sky: bright horizon, glow around the sun and the sun disk
+pub struct SunSky {
+    // towards the sun
+    pub sun_direction: Vec3,
+    pub sun_color: HdrColor,
+    // angular radius in radians
+    pub sun_radius: f64,
+    pub zenith_color: HdrColor,
+    pub horizon_color: HdrColor,
+    pub ground_color: HdrColor,
+}
+
+impl Background for SunSky {
+    fn radiance_at(&self, direction: Vec3) -> HdrColor {
+        let elevation = get_elevation(direction);
+        if elevation < 0.0 {
+            return self.ground_color;
+        }
+        let cos_sun = get_cos_between(direction, self.sun_direction);
+        if cos_sun >= self.sun_radius.cos() {
+            return self.sun_color;
+        }
+        // scattering makes the sky brighter around the sun and near the horizon
+        let glow = ((cos_sun + 1.0) / 2.0).powi(16);
+        let sky = mix(self.horizon_color, self.zenith_color, elevation.sqrt());
+        sky + (self.sun_color * 0.05 + sky) * glow
+    }
+}
+
+// equirectangular image, its center is seen along -x where the default camera looks
+pub struct EnvironmentMap {
+    image: Framebuffer,
+    // around the z axis in radians
+    pub rotation: f64,
+    pub intensity: f64,
+}
+
+impl EnvironmentMap {
+    // empty images can't be sampled
+    pub fn new(image: Framebuffer) -> io::Result<EnvironmentMap> {
+        if image.get_width() == 0 || image.get_height() == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "environment image is empty"));
+        }
+        Ok(EnvironmentMap { image, rotation: 0.0, intensity: 1.0 })
+    }
+
+    pub fn open(path: &str) -> io::Result<EnvironmentMap> {
+        EnvironmentMap::new(input::load_image(path)?)
+    }
+
+    // bilinear, wraps around horizontally and clamps at the poles
+    fn sample(&self, u: f64, v: f64) -> HdrColor {
+        let width = self.image.get_width() as i64;
+        let height = self.image.get_height() as i64;
+        let x = u * width as f64 - 0.5;
+        let y = (1.0 - v) * height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let texel = |tx: i64, ty: i64| {
+            self.image.get_pixel(tx.rem_euclid(width) as u32, ty.clamp(0, height - 1) as u32)
+        };
+        let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1, y0) * tx;
+        let bottom = texel(x0, y0 + 1) * (1.0 - tx) + texel(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn radiance_at(&self, direction: Vec3) -> HdrColor {
+        // left of the view is left in the image
+        let azimuth = (-direction.y).atan2(-direction.x) + self.rotation;
+        let u = (0.5 + azimuth / (2.0 * PI)).rem_euclid(1.0);
+        let v = 0.5 + get_elevation(direction).asin() / PI;
+        self.sample(u, v) * self.intensity
+    }
+}
+
+// sine of the angle above the horizon
+fn get_elevation(direction: Vec3) -> f64 {
+    get_cos_between(direction, Vec3::new(0.0, 0.0, 1.0))
+}
+
+fn get_cos_between(a: Vec3, b: Vec3) -> f64 {
+    let lengths = a.length() * b.length();
+    if lengths == 0.0 {
+        return 0.0;
+    }
+    (a.dot(b) / lengths).clamp(-1.0, 1.0)
+}
+
+fn mix(first: HdrColor, second: HdrColor, t: f64) -> HdrColor {
+    first * (1.0 - t) + second * t
+}
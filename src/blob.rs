@@ -0,0 +1,182 @@
+use std::cmp::Ordering;
+
+use super::{Color, ColoredPoint, Ray, Sphere};
+use math::{Normal3, Point3, Vec3};
+use shape::Shape;
+
+
+// adds (1 - (distance / radius)^2)^2 * strength to the field within the radius, negative strength carves
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BlobComponent {
+    pub center: Point3,
+    // of the influence, the surface of a lone component is inside it
+    pub radius: f64,
+    pub strength: f64,
+    pub color: Color,
+}
+
+impl BlobComponent {
+    // a lone component looks like the sphere at the threshold of the blob
+    pub fn from_sphere(sphere: &Sphere, threshold: f64) -> BlobComponent {
+        let radius = sphere.radius / (1.0 - threshold.clamp(0.0, 1.0).sqrt()).max(1e-3).sqrt();
+        BlobComponent { center: sphere.center, radius, strength: 1.0, color: sphere.color }
+    }
+
+    fn get_contribution(&self, point: Point3) -> f64 {
+        let s = (point - self.center).length_squared() / self.radius.powi(2);
+        if s >= 1.0 {
+            return 0.0;
+        }
+        self.strength * (1.0 - s).powi(2)
+    }
+
+    // coefficients of the contribution along the ray from the highest power of the distance
+    fn get_polynomial(&self, ray: Ray) -> [f64; 5] {
+        let offset = ray.start - self.center;
+        let radius_squared = self.radius.powi(2);
+        // (distance / radius)^2 = s2 * t^2 + s1 * t + s0
+        let s2 = ray.direction.length_squared() / radius_squared;
+        let s1 = 2.0 * offset.dot(ray.direction) / radius_squared;
+        let s0 = offset.length_squared() / radius_squared;
+        let w = self.strength;
+        [
+            w * s2 * s2,
+            w * 2.0 * s2 * s1,
+            w * (s1 * s1 + 2.0 * s2 * s0 - 2.0 * s2),
+            w * (2.0 * s1 * s0 - 2.0 * s1),
+            w * (s0 * s0 - 2.0 * s0 + 1.0),
+        ]
+    }
+
+    // where the ray is within the radius
+    fn get_interval(&self, ray: Ray) -> Option<(f64, f64)> {
+        let offset = ray.start - self.center;
+        let a = ray.direction.length_squared();
+        let b = offset.dot(ray.direction);
+        let c = offset.length_squared() - self.radius.powi(2);
+        let discriminant = b * b - a * c;
+        if a == 0.0 || discriminant <= 0.0 {
+            return None;
+        }
+        let d_sqrt = discriminant.sqrt();
+        Some(((-b - d_sqrt) / a, (-b + d_sqrt) / a))
+    }
+}
+
+// iso-surface where the sum of the components reaches the threshold
+#[derive(Debug, PartialEq, Clone)]
+pub struct Blob {
+    pub components: Vec<BlobComponent>,
+    pub threshold: f64,
+}
+
+impl Blob {
+    pub fn from_spheres(spheres: &[Sphere], threshold: f64) -> Blob {
+        Blob {
+            components: spheres.iter().map(|sphere| BlobComponent::from_sphere(sphere, threshold)).collect(),
+            threshold,
+        }
+    }
+
+    pub fn get_field(&self, point: Point3) -> f64 {
+        self.components.iter().map(|component| component.get_contribution(point)).sum()
+    }
+
+    // distances along the ray where the field crosses the threshold
+    fn get_roots(&self, ray: Ray) -> Vec<f64> {
+        let intervals: Vec<(f64, f64, &BlobComponent)> = self.components
+            .iter()
+            .filter_map(|component| component.get_interval(ray).map(|(start, end)| (start, end, component)))
+            .collect();
+        // the same components are active between two neighbouring bounds, so the field is one quartic there
+        let mut bounds: Vec<f64> = intervals.iter().flat_map(|&(start, end, _)| vec![start.max(0.0), end.max(0.0)]).collect();
+        bounds.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        let mut roots = vec![];
+        for window in bounds.windows(2) {
+            let (start, end) = (window[0], window[1]);
+            if end - start <= 0.0 {
+                continue;
+            }
+            let middle = (start + end) / 2.0;
+            let mut polynomial = [0.0, 0.0, 0.0, 0.0, -self.threshold];
+            for &(_, _, component) in intervals.iter().filter(|&&(from, to, _)| from <= middle && middle <= to) {
+                for (sum, coefficient) in polynomial.iter_mut().zip(component.get_polynomial(ray).iter()) {
+                    *sum += coefficient;
+                }
+            }
+            roots.extend(find_sign_changes(&polynomial, start, end));
+        }
+        roots
+    }
+
+    fn get_normal(&self, point: Point3, fallback: Vec3) -> Normal3 {
+        let mut normal = Vec3::zero();
+        for component in self.components.iter() {
+            let offset = point - component.center;
+            let s = offset.length_squared() / component.radius.powi(2);
+            if s < 1.0 {
+                // the field falls off outwards, so the normal goes against its gradient
+                normal += offset * (4.0 * component.strength * (1.0 - s) / component.radius.powi(2));
+            }
+        }
+        Normal3::try_new(normal).unwrap_or_else(|| Normal3::new(fallback))
+    }
+
+    // mixed by how much each component adds at the point
+    fn get_color(&self, point: Point3) -> Color {
+        let (mut r, mut g, mut b, mut total) = (0.0, 0.0, 0.0, 0.0);
+        for component in self.components.iter() {
+            let weight = component.get_contribution(point).max(0.0);
+            r += component.color.r as f64 * weight;
+            g += component.color.g as f64 * weight;
+            b += component.color.b as f64 * weight;
+            total += weight;
+        }
+        if total == 0.0 {
+            return self.components.first().map_or(Color::new(0, 0, 0), |component| component.color);
+        }
+        Color::new((r / total).round() as u8, (g / total).round() as u8, (b / total).round() as u8)
+    }
+}
+
+impl Shape for Blob {
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        self.get_roots(ray)
+            .into_iter()
+            .map(|t| {
+                let point = ray.start + ray.direction * t;
+                ColoredPoint::new(point, self.get_color(point), self.get_normal(point, -ray.direction))
+            })
+            .collect()
+    }
+}
+
+// samples the polynomial to bracket its roots and narrows them down by bisection,
+// which can't diverge like newton's method or lose roots to cancellation like a closed formula
+fn find_sign_changes(polynomial: &[f64; 5], start: f64, end: f64) -> Vec<f64> {
+    const SAMPLES: usize = 16;
+    let value_at = |t: f64| polynomial.iter().fold(0.0, |sum, coefficient| sum * t + coefficient);
+    let mut roots = vec![];
+    let step = (end - start) / SAMPLES as f64;
+    let mut low = start;
+    let mut low_value = value_at(low);
+    for i in 1..(SAMPLES + 1) {
+        let high = if i == SAMPLES { end } else { start + step * i as f64 };
+        let high_value = value_at(high);
+        if (low_value < 0.0) != (high_value < 0.0) {
+            let (mut a, mut b) = (low, high);
+            for _ in 0..50 {
+                let middle = (a + b) / 2.0;
+                if (value_at(middle) < 0.0) == (low_value < 0.0) {
+                    a = middle;
+                } else {
+                    b = middle;
+                }
+            }
+            roots.push((a + b) / 2.0);
+        }
+        low = high;
+        low_value = high_value;
+    }
+    roots
+}
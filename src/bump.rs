@@ -0,0 +1,100 @@
+use std::rc::Rc;
+
+use super::Color;
+use math::{Normal3, Vec3};
+use texture::{Texture, TextureCoordinates};
+
+
+// unit vectors, tangent goes along u and bitangent along v
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TangentFrame {
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    pub normal: Normal3,
+    // world distance covered by one uv unit along the tangent and the bitangent
+    pub uv_lengths: (f64, f64),
+}
+
+impl TangentFrame {
+    // tangent doesn't have to be perpendicular to the normal, only its part along the surface is used
+    pub fn new(tangent: Vec3, normal: Normal3) -> TangentFrame {
+        let mut tangent = tangent - normal * tangent.dot(*normal);
+        if tangent.length() < 1e-9 {
+            // any direction along the surface is as good
+            let axis = if normal.x.abs() < 0.9 { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::new(0.0, 1.0, 0.0) };
+            tangent = axis - normal * axis.dot(*normal);
+        }
+        let tangent = tangent.normalize();
+        TangentFrame { tangent, bitangent: normal.cross(tangent), normal, uv_lengths: (1.0, 1.0) }
+    }
+
+    pub fn to_world(&self, x: f64, y: f64, z: f64) -> Vec3 {
+        self.tangent * x + self.bitangent * y + self.normal * z
+    }
+}
+
+// perturbs shading normals without changing the geometry
+#[derive(Clone)]
+pub enum BumpMap {
+    // brightness of the texture is the height, strength is the height of white in world units
+    Height { texture: Rc<dyn Texture>, strength: f64 },
+    // colors are tangent space normals, blue goes along the surface normal, strength scales the tilt
+    Normal { texture: Rc<dyn Texture>, strength: f64 },
+}
+
+impl BumpMap {
+    // the normal of the frame when the texture is flat
+    pub fn get_normal(&self, coordinates: TextureCoordinates, frame: &TangentFrame) -> Normal3 {
+        match *self {
+            BumpMap::Height { ref texture, strength } => {
+                // central differences over the pixel footprint, or finer when it isn't known
+                let delta = coordinates.footprint.max(1e-3);
+                // solid textures look at the point, so it moves along with uv
+                let height_at = |du: f64, dv: f64| {
+                    let shifted = TextureCoordinates {
+                        point: coordinates.point + frame.to_world(du * frame.uv_lengths.0, dv * frame.uv_lengths.1, 0.0),
+                        u: coordinates.u + du,
+                        v: coordinates.v + dv,
+                        ..coordinates
+                    };
+                    get_luminance(texture.color_at(shifted)) * strength
+                };
+                let get_slope = |du: f64, dv: f64, length: f64| {
+                    // uv doesn't move anywhere at the poles of a sphere
+                    if length < 1e-9 {
+                        return 0.0;
+                    }
+                    (height_at(du, dv) - height_at(-du, -dv)) / (2.0 * delta * length)
+                };
+                let slope_u = get_slope(delta, 0.0, frame.uv_lengths.0);
+                let slope_v = get_slope(0.0, delta, frame.uv_lengths.1);
+                // slopes that overflow leave the surface flat
+                Normal3::try_new(frame.to_world(-slope_u, -slope_v, 1.0)).unwrap_or(frame.normal)
+            }
+            BumpMap::Normal { ref texture, strength } => {
+                let color = texture.color_at(coordinates);
+                let to_component = |value: u8| value as f64 / 255.0 * 2.0 - 1.0;
+                let x = to_component(color.r) * strength;
+                let y = to_component(color.g) * strength;
+                // normals pointing into the surface are flattened
+                let z = to_component(color.b).max(1e-3);
+                Normal3::try_new(frame.to_world(x, y, z)).unwrap_or(frame.normal)
+            }
+        }
+    }
+}
+
+fn get_luminance(color: Color) -> f64 {
+    (0.2126 * color.r as f64 + 0.7152 * color.g as f64 + 0.0722 * color.b as f64) / 255.0
+}
+
+// shading ignores the angle to the light, so bumps only scale the brightness
+// by how much they turn the surface towards the light or away from it
+pub fn get_lighting_factor(shading_normal: Normal3, normal: Normal3, to_light: Vec3) -> f64 {
+    if shading_normal == normal {
+        return 1.0;
+    }
+    let to_light = to_light.normalize();
+    let half_lambert = |n: Normal3| 1.0 + n.dot(to_light);
+    half_lambert(shading_normal) / half_lambert(normal).max(0.1)
+}
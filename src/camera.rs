@@ -0,0 +1,176 @@
+use std::f64::consts::PI;
+
+use super::{get_distance, Ray};
+use math::{Point3, Vec3};
+use random::Rng;
+
+
+pub const PROJECTION_NAMES: [&str; 4] = ["perspective", "orthographic", "fisheye", "equirectangular"];
+
+// how points of the screen become rays, all projections are centered where the eye looks at the screen
+// and measure the screen in distances from the eye to it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Projection {
+    Perspective,
+    // parallel rays, scale is the world size of one unit of the screen
+    Orthographic { scale: f64 },
+    // equidistant, the circle as far from the center as the eye is from the screen sees the field of view
+    Fisheye { field_of_view: f64 },
+    // 360 by 180 degrees in the 2:1 rectangle as high as twice the distance from the eye to the screen
+    Equirectangular,
+}
+
+impl Projection {
+    // orthographic keeps the size of the screen, fisheye sees a half sphere
+    pub fn from_name(name: &str) -> Option<Projection> {
+        match name {
+            "perspective" => Some(Projection::Perspective),
+            "orthographic" => Some(Projection::Orthographic { scale: 1.0 }),
+            "fisheye" => Some(Projection::Fisheye { field_of_view: PI }),
+            "equirectangular" => Some(Projection::Equirectangular),
+            _ => None,
+        }
+    }
+
+    // of the image with the height, panoramas see twice as far around as up and down
+    pub fn get_image_width(&self, height: u32) -> u32 {
+        match *self {
+            Projection::Equirectangular => 2 * height,
+            _ => height,
+        }
+    }
+}
+
+// lens in front of the eye, parallel to the screen
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Camera {
+    // 0.0 is a pinhole that keeps everything sharp
+    pub aperture_radius: f64,
+    // from the eye towards the screen, things this far away are sharp
+    pub focus_distance: f64,
+    pub projection: Projection,
+    // polygonal aperture with this many blades, round when None
+    pub blades: Option<u32>,
+    // of the first blade corner, in radians
+    pub blade_rotation: f64,
+    // rays averaged for every pixel, more of them also smooth the edges
+    pub samples: u32,
+    pub seed: u64,
+    // rays are shot at random times between these two, moving things blur
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    // how far the eye goes in one unit of time after the shutter opens, the screen moves along with it
+    pub eye_motion: Vec3,
+}
+
+impl Camera {
+    pub fn pinhole() -> Camera {
+        Camera {
+            aperture_radius: 0.0,
+            focus_distance: 1.0,
+            projection: Projection::Perspective,
+            blades: None,
+            blade_rotation: 0.0,
+            samples: 1,
+            seed: 0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            eye_motion: Vec3::zero(),
+        }
+    }
+
+    pub fn thin_lens(aperture_radius: f64, focus_distance: f64) -> Camera {
+        Camera { aperture_radius, focus_distance, ..Camera::pinhole() }
+    }
+
+    pub fn is_pinhole(&self) -> bool {
+        self.aperture_radius <= 0.0
+    }
+
+    // ray through a point of the screen at x = 0 before the lens bends it, None outside of the picture
+    pub fn get_pinhole_ray(&self, eye: Point3, y: f64, z: f64) -> Option<Ray> {
+        let screen_distance = eye.x.abs();
+        let axis = Vec3::new(if eye.x >= 0.0 { -1.0 } else { 1.0 }, 0.0, 0.0);
+        let (dy, dz) = (y - eye.y, z - eye.z);
+        match self.projection {
+            Projection::Perspective => {
+                let point_at_screen = Point3::new(0.0, y, z);
+                let mut ray = Ray::from_to(eye, point_at_screen);
+                // pixels are one unit apart on the screen
+                ray.spread = 1.0 / get_distance(eye, point_at_screen);
+                Some(ray)
+            }
+            Projection::Orthographic { scale } => Some(Ray::new(eye + Vec3::new(0.0, dy * scale, dz * scale), axis)),
+            Projection::Fisheye { field_of_view } => {
+                let radius = (dy * dy + dz * dz).sqrt() / screen_distance;
+                if radius > 1.0 {
+                    return None;
+                }
+                let (sin, cos) = (radius * field_of_view / 2.0).sin_cos();
+                let side = Vec3::new(0.0, dy, dz).normalize();
+                let spread = field_of_view / 2.0 / screen_distance;
+                Some(Ray { spread, ..Ray::new(eye, axis * cos + side * sin) })
+            }
+            Projection::Equirectangular => {
+                if dy.abs() > 2.0 * screen_distance || dz.abs() > screen_distance {
+                    return None;
+                }
+                let longitude = dy / screen_distance * PI / 2.0;
+                let latitude = dz / screen_distance * PI / 2.0;
+                let direction = (axis * longitude.cos() + Vec3::new(0.0, longitude.sin(), 0.0)) * latitude.cos()
+                    + Vec3::new(0.0, 0.0, latitude.sin());
+                Some(Ray { spread: PI / 2.0 / screen_distance, ..Ray::new(eye, direction) })
+            }
+        }
+    }
+
+    // doesn't draw a random number when the shutter is closed
+    pub fn sample_time(&self, rng: &mut Rng) -> f64 {
+        if self.shutter_close <= self.shutter_open {
+            return self.shutter_open;
+        }
+        self.shutter_open + (self.shutter_close - self.shutter_open) * rng.next_f64()
+    }
+
+    // uniformly distributed offset from the center of the lens
+    pub fn sample_aperture(&self, rng: &mut Rng) -> (f64, f64) {
+        match self.blades {
+            Some(blades) if blades >= 3 => {
+                // a random triangle between the center and two neighbouring corners, then a point in it
+                let corner = (rng.next_f64() * blades as f64).floor().min(blades as f64 - 1.0);
+                let angle = 2.0 * PI / blades as f64;
+                let first = corner * angle + self.blade_rotation;
+                let (mut a, mut b) = (rng.next_f64(), rng.next_f64());
+                if a + b > 1.0 {
+                    a = 1.0 - a;
+                    b = 1.0 - b;
+                }
+                let x = a * first.cos() + b * (first + angle).cos();
+                let y = a * first.sin() + b * (first + angle).sin();
+                (x * self.aperture_radius, y * self.aperture_radius)
+            }
+            _ => {
+                let radius = self.aperture_radius * rng.next_f64().sqrt();
+                let angle = 2.0 * PI * rng.next_f64();
+                (radius * angle.cos(), radius * angle.sin())
+            }
+        }
+    }
+
+    // the pinhole ray starts at the eye and goes through the screen at x = 0, the lens ray
+    // leaves from a random point of the lens and meets it at the focus distance
+    pub fn get_lens_ray(&self, pinhole_ray: Ray, rng: &mut Rng) -> Ray {
+        if self.is_pinhole() {
+            return pinhole_ray;
+        }
+        let axis = Vec3::new(if pinhole_ray.start.x >= 0.0 { -1.0 } else { 1.0 }, 0.0, 0.0);
+        let along_axis = pinhole_ray.direction.dot(axis);
+        if along_axis <= 0.0 {
+            return pinhole_ray;
+        }
+        let focus_point = pinhole_ray.start + pinhole_ray.direction * (self.focus_distance / along_axis);
+        let (y, z) = self.sample_aperture(rng);
+        let lens_point = pinhole_ray.start + Vec3::new(0.0, y, z);
+        Ray { spread: pinhole_ray.spread, ..Ray::from_to(lens_point, focus_point) }
+    }
+}
@@ -0,0 +1,108 @@
+use super::HdrColor;
+use aov::Aov;
+use framebuffer::{Framebuffer, Layer};
+
+
+// weights of the edge stopping functions, smaller sigma keeps more edges
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct DenoiseOptions {
+    // every iteration doubles the filter radius
+    pub iterations: u32,
+    pub color_sigma: f64,
+    pub normal_sigma: f64,
+    pub albedo_sigma: f64,
+    // relative to the depth of the pixel
+    pub depth_sigma: f64,
+}
+
+impl Default for DenoiseOptions {
+    fn default() -> DenoiseOptions {
+        DenoiseOptions {
+            iterations: 5,
+            color_sigma: 0.5,
+            normal_sigma: 0.3,
+            albedo_sigma: 0.1,
+            depth_sigma: 0.05,
+        }
+    }
+}
+
+// B3 spline
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// edge avoiding a-trous wavelet filter of the main image
+// normal, albedo and depth layers guide it when the framebuffer has them, other layers are copied as is
+pub fn denoise(framebuffer: &Framebuffer, options: &DenoiseOptions) -> Framebuffer {
+    let width = framebuffer.get_width();
+    let height = framebuffer.get_height();
+    let normals = framebuffer.get_layer(Aov::Normal.get_name());
+    let albedos = framebuffer.get_layer(Aov::Albedo.get_name());
+    let depths = framebuffer.get_layer(Aov::Depth.get_name());
+    let mut pixels = framebuffer.get_pixels().to_vec();
+    for iteration in 0..options.iterations {
+        // later iterations see an already smoothed image
+        let color_sigma = options.color_sigma / 2.0_f64.powi(iteration as i32);
+        let step = 1_i64 << iteration;
+        let mut filtered = Vec::with_capacity(pixels.len());
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                let center = pixels[(y * width as i64 + x) as usize];
+                let mut sum = HdrColor::new(0.0, 0.0, 0.0);
+                let mut total_weight = 0.0;
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let qx = x + (i as i64 - 2) * step;
+                        let qy = y + (j as i64 - 2) * step;
+                        if qx < 0 || qy < 0 || qx >= width as i64 || qy >= height as i64 {
+                            continue;
+                        }
+                        let color = pixels[(qy * width as i64 + qx) as usize];
+                        let mut weight = kx * ky * get_weight(get_squared_distance(center, color), color_sigma);
+                        let (p, q) = ((x as u32, y as u32), (qx as u32, qy as u32));
+                        if let Some(layer) = normals {
+                            weight *= get_weight(get_layer_distance(layer, p, q), options.normal_sigma);
+                        }
+                        if let Some(layer) = albedos {
+                            weight *= get_weight(get_layer_distance(layer, p, q), options.albedo_sigma);
+                        }
+                        if let Some(layer) = depths {
+                            weight *= get_weight(get_depth_distance(layer, p, q), options.depth_sigma);
+                        }
+                        sum = sum + color * weight;
+                        total_weight += weight;
+                    }
+                }
+                // center pixel always has a positive weight
+                filtered.push(sum * (1.0 / total_weight));
+            }
+        }
+        pixels = filtered;
+    }
+    let mut result = framebuffer.clone();
+    for (i, pixel) in pixels.into_iter().enumerate() {
+        result.set_pixel(i as u32 % width, i as u32 / width, pixel);
+    }
+    result
+}
+
+fn get_weight(squared_distance: f64, sigma: f64) -> f64 {
+    (-squared_distance / sigma.powi(2)).exp()
+}
+
+fn get_squared_distance(a: HdrColor, b: HdrColor) -> f64 {
+    (a.r - b.r).powi(2) + (a.g - b.g).powi(2) + (a.b - b.b).powi(2)
+}
+
+fn get_layer_distance(layer: &Layer, p: (u32, u32), q: (u32, u32)) -> f64 {
+    get_squared_distance(layer.get_pixel(p.0, p.1), layer.get_pixel(q.0, q.1))
+}
+
+fn get_depth_distance(layer: &Layer, p: (u32, u32), q: (u32, u32)) -> f64 {
+    let a = layer.get_pixel(p.0, p.1).r;
+    let b = layer.get_pixel(q.0, q.1).r;
+    if a.is_infinite() || b.is_infinite() {
+        // sky is only similar to sky
+        return if a == b { 0.0 } else { f64::INFINITY };
+    }
+    ((a - b) / a.max(b).max(1e-9)).powi(2)
+}
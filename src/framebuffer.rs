@@ -0,0 +1,193 @@
+use super::{Color, HdrColor, ToneMapping};
+
+
+// extra named image rendered alongside the main one, e.g. depth or normals
+#[derive(Debug, Clone)]
+pub struct Layer {
+    pub name: String,
+    width: u32,
+    pixels: Vec<HdrColor>,
+}
+
+impl Layer {
+    fn new(name: &str, width: u32, height: u32) -> Layer {
+        Layer {
+            name: name.to_string(),
+            width,
+            pixels: vec![HdrColor::new(0.0, 0.0, 0.0); (width * height) as usize],
+        }
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> HdrColor {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: HdrColor) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    pub fn get_pixels(&self) -> &[HdrColor] {
+        &self.pixels
+    }
+}
+
+// rectangle of pixels from the top left corner, the right and bottom bounds are outside of it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Region {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl Region {
+    pub fn new(left: u32, top: u32, right: u32, bottom: u32) -> Region {
+        assert!(left <= right && top <= bottom, "region can't be turned inside out");
+        Region { left, top, right, bottom }
+    }
+
+    pub fn full(width: u32, height: u32) -> Region {
+        Region::new(0, 0, width, height)
+    }
+
+    // band of rows number index out of count, from the top, counting from 1
+    pub fn tile(index: u32, count: u32, width: u32, height: u32) -> Region {
+        assert!(index >= 1 && index <= count, "there is no tile {} of {}", index, count);
+        let get_row = |index: u32| (index as u64 * height as u64 / count as u64) as u32;
+        Region::new(0, get_row(index - 1), width, get_row(index))
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.right - self.left
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.bottom - self.top
+    }
+
+    pub fn is_within(&self, width: u32, height: u32) -> bool {
+        self.right <= width && self.bottom <= height
+    }
+}
+
+// float image with the origin in the top left corner
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<HdrColor>,
+    layers: Vec<Layer>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![HdrColor::new(0.0, 0.0, 0.0); (width * height) as usize],
+            layers: vec![],
+        }
+    }
+
+    // pixels go row by row, starting from the top left corner
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<HdrColor>) -> Framebuffer {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        Framebuffer { width, height, pixels, layers: vec![] }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> HdrColor {
+        self.pixels[self.get_index(x, y)]
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: HdrColor) {
+        let index = self.get_index(x, y);
+        self.pixels[index] = color;
+    }
+
+    // row by row, starting from the top left corner
+    pub fn get_pixels(&self) -> &[HdrColor] {
+        &self.pixels
+    }
+
+    // existing layer with the same name is reset
+    pub fn add_layer(&mut self, name: &str) -> &mut Layer {
+        self.layers.retain(|layer| layer.name != name);
+        self.layers.push(Layer::new(name, self.width, self.height));
+        self.layers.last_mut().unwrap()
+    }
+
+    pub fn get_layer(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn get_layer_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    pub fn remove_layer(&mut self, name: &str) {
+        self.layers.retain(|layer| layer.name != name);
+    }
+
+    pub fn get_layers_mut(&mut self) -> &mut [Layer] {
+        &mut self.layers
+    }
+
+    pub fn get_layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    // the pixels and layers inside the region
+    pub fn crop(&self, region: Region) -> Framebuffer {
+        assert!(region.is_within(self.width, self.height), "{:?} is outside of the framebuffer", region);
+        let mut cropped = Framebuffer::new(region.get_width(), region.get_height());
+        for name in self.layers.iter().map(|layer| layer.name.clone()) {
+            cropped.add_layer(&name);
+        }
+        cropped.paste_from(self, region, 0, 0);
+        cropped
+    }
+
+    // puts the other framebuffer with its top left corner at left, top,
+    // layers go to the layers with the same names and are skipped when there are none
+    pub fn paste(&mut self, other: &Framebuffer, left: u32, top: u32) {
+        self.paste_from(other, Region::full(other.width, other.height), left, top);
+    }
+
+    fn paste_from(&mut self, other: &Framebuffer, region: Region, left: u32, top: u32) {
+        assert!(
+            left + region.get_width() <= self.width && top + region.get_height() <= self.height,
+            "pasted pixels go outside of the framebuffer",
+        );
+        for y in 0..region.get_height() {
+            for x in 0..region.get_width() {
+                self.set_pixel(left + x, top + y, other.get_pixel(region.left + x, region.top + y));
+            }
+        }
+        for other_layer in &other.layers {
+            if let Some(layer) = self.get_layer_mut(&other_layer.name) {
+                for y in 0..region.get_height() {
+                    for x in 0..region.get_width() {
+                        layer.set_pixel(left + x, top + y, other_layer.get_pixel(region.left + x, region.top + y));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn to_colors(&self, tone_mapping: ToneMapping, exposure: f64) -> Vec<Color> {
+        self.pixels.iter().map(|pixel| tone_mapping.apply(*pixel, exposure)).collect()
+    }
+
+    fn get_index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "({}, {}) is outside of the framebuffer", x, y);
+        (y * self.width + x) as usize
+    }
+}
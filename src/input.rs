@@ -0,0 +1,425 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+
+use png;
+
+use super::HdrColor;
+use output;
+use framebuffer::Framebuffer;
+use output::ImageFormat;
+
+
+// 8 bit images are mapped to 0.0..1.0 without any gamma correction, like the output does
+pub fn load_image(path: &str) -> io::Result<Framebuffer> {
+    let format = ImageFormat::from_path(path)
+        .ok_or_else(|| get_invalid_data_error(&format!("can't guess image format of {}", path)))?;
+    let mut reader = BufReader::new(File::open(path)?);
+    match format {
+        ImageFormat::Png => read_png(reader),
+        ImageFormat::Bmp => read_bmp(&mut reader),
+        ImageFormat::Ppm => read_ppm(&mut reader),
+        ImageFormat::Pfm => read_pfm(&mut reader),
+        ImageFormat::Hdr => read_hdr(&mut reader),
+        ImageFormat::Exr(_) => read_exr(&mut reader),
+    }
+}
+
+fn get_invalid_data_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// a broken header can't ask for more memory than an image of this many pixels takes
+const MAX_PIXEL_COUNT: u32 = 1 << 28;
+
+fn get_pixel_count(width: u32, height: u32) -> io::Result<usize> {
+    if width == 0 || height == 0 {
+        return Err(get_invalid_data_error("image is empty"));
+    }
+    match width.checked_mul(height) {
+        Some(count) if count <= MAX_PIXEL_COUNT => Ok(count as usize),
+        _ => Err(get_invalid_data_error(&format!("{}x{} image is too large", width, height))),
+    }
+}
+
+// grows with the data instead of trusting the header, so a truncated file fails early
+fn read_bytes<R: Read>(reader: &mut R, count: usize) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    reader.take(count as u64).read_to_end(&mut data)?;
+    if data.len() < count {
+        return Err(get_invalid_data_error("image is truncated"));
+    }
+    Ok(data)
+}
+
+fn get_color(r: u8, g: u8, b: u8) -> HdrColor {
+    HdrColor::new(r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0)
+}
+
+fn read_png<R: Read>(reader: R) -> io::Result<Framebuffer> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data)?;
+    let channels = info.color_type.samples();
+    let pixels = data[..info.buffer_size()]
+        .chunks(channels)
+        .map(|p| if channels < 3 { get_color(p[0], p[0], p[0]) } else { get_color(p[0], p[1], p[2]) })
+        .collect();
+    Ok(Framebuffer::from_pixels(info.width, info.height, pixels))
+}
+
+// uncompressed 24 and 32 bit bitmaps
+fn read_bmp<R: Read>(reader: &mut R) -> io::Result<Framebuffer> {
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    if data.len() < 54 || &data[..2] != b"BM" {
+        return Err(get_invalid_data_error("not a bmp image"));
+    }
+    let read_u32 = |offset: usize| u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+    let pixel_offset = read_u32(10) as usize;
+    let width = read_u32(18) as i32;
+    let height = read_u32(22) as i32;
+    // only the height may be negative, for scanlines going from the top
+    if width <= 0 {
+        return Err(get_invalid_data_error("bmp image has no width"));
+    }
+    let pixel_count = get_pixel_count(width as u32, height.unsigned_abs())?;
+    let bits_per_pixel = u16::from_le_bytes([data[28], data[29]]);
+    let compression = read_u32(30);
+    if (bits_per_pixel != 24 && bits_per_pixel != 32) || compression != 0 {
+        return Err(get_invalid_data_error("only uncompressed 24 and 32 bit bmp images are supported"));
+    }
+    let bytes_per_pixel = bits_per_pixel as usize / 8;
+    let row_size = (bytes_per_pixel * width as usize).div_ceil(4) * 4;
+    let rows = height.unsigned_abs() as usize;
+    if data.len() < pixel_offset + row_size * rows {
+        return Err(get_invalid_data_error("bmp image is truncated"));
+    }
+    let mut pixels = Vec::with_capacity(pixel_count);
+    for row in 0..rows {
+        // positive height means that scanlines go from the bottom to the top
+        let scanline = if height > 0 { rows - 1 - row } else { row };
+        let start = pixel_offset + scanline * row_size;
+        for p in data[start..start + bytes_per_pixel * width as usize].chunks(bytes_per_pixel) {
+            pixels.push(get_color(p[2], p[1], p[0]));
+        }
+    }
+    Ok(Framebuffer::from_pixels(width as u32, rows as u32, pixels))
+}
+
+// whitespace separated header values, comments are not supported
+fn read_header_values<R: BufRead>(reader: &mut R, count: usize) -> io::Result<Vec<String>> {
+    let mut values = vec![];
+    let mut current = String::new();
+    while values.len() < count {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        if (byte[0] as char).is_whitespace() {
+            if !current.is_empty() {
+                values.push(current.clone());
+                current.clear();
+            }
+        } else {
+            current.push(byte[0] as char);
+        }
+    }
+    Ok(values)
+}
+
+fn parse_header_value<T: ::std::str::FromStr>(value: &str) -> io::Result<T> {
+    value.parse().map_err(|_| get_invalid_data_error(&format!("bad header value {}", value)))
+}
+
+fn read_ppm<R: BufRead>(reader: &mut R) -> io::Result<Framebuffer> {
+    let header = read_header_values(reader, 4)?;
+    if header[0] != "P6" || header[3] != "255" {
+        return Err(get_invalid_data_error("only binary 8 bit ppm images are supported"));
+    }
+    let width: u32 = parse_header_value(&header[1])?;
+    let height: u32 = parse_header_value(&header[2])?;
+    let data = read_bytes(reader, get_pixel_count(width, height)? * 3)?;
+    let pixels = data.chunks(3).map(|p| get_color(p[0], p[1], p[2])).collect();
+    Ok(Framebuffer::from_pixels(width, height, pixels))
+}
+
+fn read_pfm<R: BufRead>(reader: &mut R) -> io::Result<Framebuffer> {
+    let header = read_header_values(reader, 4)?;
+    if header[0] != "PF" {
+        return Err(get_invalid_data_error("only color pfm images are supported"));
+    }
+    let width: u32 = parse_header_value(&header[1])?;
+    let height: u32 = parse_header_value(&header[2])?;
+    let scale: f64 = parse_header_value(&header[3])?;
+    let pixel_count = get_pixel_count(width, height)?;
+    let data = read_bytes(reader, pixel_count * 12)?;
+    let values: Vec<f64> = data.chunks(4)
+        .map(|b| {
+            let bytes = [b[0], b[1], b[2], b[3]];
+            let value = if scale < 0.0 { f32::from_le_bytes(bytes) } else { f32::from_be_bytes(bytes) };
+            value as f64
+        })
+        .collect();
+    // scanlines go from the bottom to the top
+    let mut pixels = Vec::with_capacity(pixel_count);
+    for scanline in values.chunks(3 * width as usize).rev() {
+        for p in scanline.chunks(3) {
+            pixels.push(HdrColor::new(p[0], p[1], p[2]));
+        }
+    }
+    Ok(Framebuffer::from_pixels(width, height, pixels))
+}
+
+// radiance images with -Y height +X width orientation, flat or run length encoded scanlines
+fn read_hdr<R: BufRead>(reader: &mut R) -> io::Result<Framebuffer> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(get_invalid_data_error("not a radiance hdr image"));
+    }
+    // header ends with an empty line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(get_invalid_data_error("hdr header is truncated"));
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(get_invalid_data_error(&format!("unsupported hdr {}", line)));
+        }
+    }
+    let header = read_header_values(reader, 4)?;
+    if header[0] != "-Y" || header[2] != "+X" {
+        return Err(get_invalid_data_error("only -Y +X hdr images are supported"));
+    }
+    let height: u32 = parse_header_value(&header[1])?;
+    let width: u32 = parse_header_value(&header[3])?;
+    get_pixel_count(width, height)?;
+    // only as much as the scanlines read so far, they may be missing
+    let mut pixels = vec![];
+    for _ in 0..height {
+        for rgbe in read_hdr_scanline(reader, width as usize)? {
+            pixels.push(output::from_rgbe(rgbe));
+        }
+    }
+    Ok(Framebuffer::from_pixels(width, height, pixels))
+}
+
+fn read_hdr_scanline<R: Read>(reader: &mut R, width: usize) -> io::Result<Vec<[u8; 4]>> {
+    let mut first = [0; 4];
+    reader.read_exact(&mut first)?;
+    let is_rle = (8..0x8000).contains(&width) && first[0] == 2 && first[1] == 2 && first[2] < 128;
+    if !is_rle {
+        let mut scanline = vec![first];
+        for _ in 1..width {
+            let mut rgbe = [0; 4];
+            reader.read_exact(&mut rgbe)?;
+            scanline.push(rgbe);
+        }
+        return Ok(scanline);
+    }
+    if ((first[2] as usize) << 8 | first[3] as usize) != width {
+        return Err(get_invalid_data_error("hdr scanline has a wrong width"));
+    }
+    // components go one after another
+    let mut components = vec![];
+    for _ in 0..4 {
+        components.push(read_hdr_runs(reader, width)?);
+    }
+    Ok((0..width).map(|x| [components[0][x], components[1][x], components[2][x], components[3][x]]).collect())
+}
+
+// runs of one repeated byte or literal runs
+fn read_hdr_runs<R: Read>(reader: &mut R, width: usize) -> io::Result<Vec<u8>> {
+    let mut values = Vec::with_capacity(width);
+    while values.len() < width {
+        let mut count = [0];
+        reader.read_exact(&mut count)?;
+        let (count, is_repeated) = if count[0] > 128 { (count[0] as usize - 128, true) } else { (count[0] as usize, false) };
+        if count == 0 || values.len() + count > width {
+            return Err(get_invalid_data_error("bad hdr scanline run"));
+        }
+        if is_repeated {
+            let mut value = [0];
+            reader.read_exact(&mut value)?;
+            values.extend(vec![value[0]; count]);
+        } else {
+            let mut run = vec![0; count];
+            reader.read_exact(&mut run)?;
+            values.extend(run);
+        }
+    }
+    Ok(values)
+}
+
+// uncompressed single part scanline OpenEXR images with half or float channels, like the output writes,
+// R, G and B are the pixels and channels like depth.R go to the layer in front of the dot
+fn read_exr<R: Read>(reader: &mut R) -> io::Result<Framebuffer> {
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+    if data.len() < 8 || data[..4] != [0x76, 0x2f, 0x31, 0x01] {
+        return Err(get_invalid_data_error("not an exr image"));
+    }
+    // tiled, deep and multipart images
+    if data[4] != 2 || data[5] & 0x1a != 0 {
+        return Err(get_invalid_data_error("only scanline exr images are supported"));
+    }
+    let mut position = 8;
+    let mut channels = None;
+    let mut compression = None;
+    let mut window = None;
+    loop {
+        let name = read_exr_string(&data, &mut position)?;
+        if name.is_empty() {
+            break;
+        }
+        read_exr_string(&data, &mut position)?;
+        let size = read_exr_i32(&data, &mut position)?;
+        let value = data.get(position..position + size.max(0) as usize).ok_or_else(get_truncated_exr_error)?;
+        position += value.len();
+        match name.as_str() {
+            "channels" => channels = Some(read_exr_channels(value)?),
+            "compression" => compression = value.first().cloned(),
+            "dataWindow" if value.len() == 16 => {
+                let mut window_position = 0;
+                let mut bounds = [0; 4];
+                for bound in bounds.iter_mut() {
+                    *bound = read_exr_i32(value, &mut window_position)? as i64;
+                }
+                window = Some(bounds);
+            }
+            _ => {}
+        }
+    }
+    let channels = channels.ok_or_else(|| get_invalid_data_error("exr image has no channels"))?;
+    if compression != Some(0) {
+        return Err(get_invalid_data_error("only uncompressed exr images are supported"));
+    }
+    let [left, top, right, bottom] = window.ok_or_else(|| get_invalid_data_error("exr image has no data window"))?;
+    let to_size = |size: i64| if size > 0 && size <= u32::MAX as i64 { Some(size as u32) } else { None };
+    let (width, height) = match (to_size(right - left + 1), to_size(bottom - top + 1)) {
+        (Some(width), Some(height)) => (width, height),
+        _ => return Err(get_invalid_data_error("exr image is empty")),
+    };
+    let pixel_count = get_pixel_count(width, height)?;
+    let pixel_size: usize = channels.iter().map(|channel| channel.1).sum();
+    // the offset table and every scanline with its y and size
+    let scanline_size = (width as usize).checked_mul(pixel_size);
+    let total_size = scanline_size.and_then(|size| (size + 16).checked_mul(height as usize));
+    let scanline_size = match total_size {
+        Some(total_size) if total_size <= data.len() - position => scanline_size.unwrap(),
+        _ => return Err(get_truncated_exr_error()),
+    };
+    position += height as usize * 8;
+    let mut pixels = vec![HdrColor::new(0.0, 0.0, 0.0); pixel_count];
+    let mut layers: Vec<(String, Vec<HdrColor>)> = vec![];
+    for _ in 0..height {
+        let row = read_exr_i32(&data, &mut position)? as i64 - top;
+        let size = read_exr_i32(&data, &mut position)?;
+        if row < 0 || row >= height as i64 || size as usize != scanline_size {
+            return Err(get_invalid_data_error("bad exr scanline"));
+        }
+        for &(ref name, value_size) in &channels {
+            let values = &data[position..position + width as usize * value_size];
+            position += values.len();
+            let (layer_name, component) = match name.rfind('.') {
+                Some(dot) => (Some(&name[..dot]), &name[dot + 1..]),
+                None => (None, &name[..]),
+            };
+            let component = match component {
+                "R" => 0,
+                "G" => 1,
+                "B" => 2,
+                _ => continue,
+            };
+            let target = match layer_name {
+                None => &mut pixels,
+                Some(layer_name) => {
+                    if !layers.iter().any(|layer| layer.0 == layer_name) {
+                        layers.push((layer_name.to_string(), vec![HdrColor::new(0.0, 0.0, 0.0); pixel_count]));
+                    }
+                    &mut layers.iter_mut().find(|layer| layer.0 == layer_name).unwrap().1
+                }
+            };
+            for (x, bytes) in values.chunks(value_size).enumerate() {
+                let value = if value_size == 2 {
+                    from_half(u16::from_le_bytes([bytes[0], bytes[1]]))
+                } else {
+                    f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+                } as f64;
+                let pixel = &mut target[row as usize * width as usize + x];
+                match component {
+                    0 => pixel.r = value,
+                    1 => pixel.g = value,
+                    _ => pixel.b = value,
+                }
+            }
+        }
+    }
+    let mut framebuffer = Framebuffer::from_pixels(width, height, pixels);
+    for (name, layer_pixels) in layers {
+        let layer = framebuffer.add_layer(&name);
+        for (index, pixel) in layer_pixels.into_iter().enumerate() {
+            layer.set_pixel(index as u32 % width, index as u32 / width, pixel);
+        }
+    }
+    Ok(framebuffer)
+}
+
+// names with the size of their values in bytes
+fn read_exr_channels(value: &[u8]) -> io::Result<Vec<(String, usize)>> {
+    let mut position = 0;
+    let mut channels = vec![];
+    loop {
+        let name = read_exr_string(value, &mut position)?;
+        if name.is_empty() {
+            return Ok(channels);
+        }
+        let pixel_type = read_exr_i32(value, &mut position)?;
+        // linear flag and reserved bytes
+        position += 4;
+        let sampling = (read_exr_i32(value, &mut position)?, read_exr_i32(value, &mut position)?);
+        let size = match pixel_type {
+            1 => 2,
+            2 => 4,
+            _ => return Err(get_invalid_data_error("only half and float exr channels are supported")),
+        };
+        if sampling != (1, 1) {
+            return Err(get_invalid_data_error("subsampled exr channels are not supported"));
+        }
+        channels.push((name, size));
+    }
+}
+
+fn read_exr_string(data: &[u8], position: &mut usize) -> io::Result<String> {
+    let rest = data.get(*position..).unwrap_or(&[]);
+    let end = rest.iter().position(|&byte| byte == 0).ok_or_else(get_truncated_exr_error)?;
+    *position += end + 1;
+    String::from_utf8(rest[..end].to_vec()).map_err(|_| get_invalid_data_error("exr name isn't utf-8"))
+}
+
+fn read_exr_i32(data: &[u8], position: &mut usize) -> io::Result<i32> {
+    let bytes = data.get(*position..*position + 4).ok_or_else(get_truncated_exr_error)?;
+    *position += 4;
+    Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn get_truncated_exr_error() -> io::Error {
+    get_invalid_data_error("exr image is truncated")
+}
+
+// IEEE 754 half precision float
+fn from_half(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10) & 0x1f;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2.0_f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2.0_f32.powi(exponent as i32 - 15),
+    }
+}
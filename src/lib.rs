@@ -1,5 +1,47 @@
+extern crate png;
+
+use std::cmp::Ordering;
+use std::f64::consts::PI;
 use std::fmt;
-use std::ops::{Add, Mul, Sub};
+use std::ops::{Add, Mul, Range};
+use std::rc::Rc;
+
+pub mod animation;
+pub mod aov;
+pub mod background;
+pub mod blob;
+pub mod bump;
+pub mod camera;
+pub mod denoise;
+pub mod framebuffer;
+pub mod input;
+pub mod math;
+pub mod output;
+pub mod primitives;
+pub mod progressive;
+pub mod random;
+pub mod sdf;
+pub mod shape;
+pub mod texture;
+pub mod tonemap;
+pub mod transform;
+
+use bump::TangentFrame;
+use progressive::Accumulation;
+use random::Rng;
+use texture::{Checker, PlanarMapping};
+
+pub use animation::{AnimationError, Interpolation, Keyframe, SceneAnimation, Track};
+pub use aov::Aov;
+pub use background::Background;
+pub use bump::BumpMap;
+pub use camera::{Camera, Projection};
+pub use framebuffer::{Framebuffer, Region};
+pub use math::{Mat4, Normal3, Point3, Vec3};
+pub use shape::{Csg, CsgOperation, Instance, MovingInstance, Shape, Textured};
+pub use texture::{Texture, TextureCoordinates};
+pub use tonemap::ToneMapping;
+pub use transform::Transform;
 
 
 pub trait PointInSpace {
@@ -9,10 +51,11 @@ pub trait PointInSpace {
 }
 
 
-pub fn get_closest_point<T, S>(point: S, points: &Vec<T>) -> Option<T> where T: PointInSpace + Copy, S: PointInSpace + Copy {
-    let mut clone = points.clone();
-    clone.sort_by(|a, b| (&get_distance(*a, point)).partial_cmp(&get_distance(*b, point)).unwrap());
-    if clone.len() == 0 {
+pub fn get_closest_point<T, S>(point: S, points: &[T]) -> Option<T> where T: PointInSpace + Copy, S: PointInSpace + Copy {
+    let mut clone = points.to_vec();
+    // nan distances of broken geometry mustn't stop the render
+    clone.sort_by(|a, b| get_distance(*a, point).partial_cmp(&get_distance(*b, point)).unwrap_or(Ordering::Equal));
+    if clone.is_empty() {
         None
     } else {
         Some(clone[0])
@@ -24,20 +67,7 @@ pub fn get_distance<S, T>(a: S, b: T) -> f64 where S: PointInSpace, T: PointInSp
     sum.sqrt()
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub struct Point {
-    pub x: f64,
-    pub y: f64,
-    pub z: f64,
-}
-
-impl Point {
-    pub fn new(x: f64, y: f64, z: f64) -> Point {
-        Point { x: x, y: y, z: z }
-    }
-}
-
-impl PointInSpace for Point {
+impl PointInSpace for Point3 {
     fn get_x(&self) -> f64 {
         self.x
     }
@@ -51,59 +81,79 @@ impl PointInSpace for Point {
     }
 }
 
-impl fmt::Display for Point {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Point(x={}, y={}, z={})", self.x, self.y, self.z)
-    }
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
 }
 
-impl Sub for Point {
-    type Output = Point;
-
-    fn sub(self, other: Point) -> Point {
-        Point::new(self.x - other.x, self.y - other.y, self.z - other.z)
+impl Color {
+    pub fn new(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b }
     }
 }
 
-impl Add for Point {
-    type Output = Point;
-
-    fn add(self, other: Point) -> Point {
-        Point::new(self.x + other.x, self.y + other.y, self.z + other.z)
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Color(r={}, g={}, b={})", self.r, self.g, self.b)
     }
 }
 
-impl Mul<f64> for Point {
-    type Output = Point;
+/// Linear, unclamped color. 1.0 in a channel corresponds to 255 in `Color`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct HdrColor {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+}
+
+impl HdrColor {
+    pub fn new(r: f64, g: f64, b: f64) -> HdrColor {
+        HdrColor { r, g, b }
+    }
 
-    fn mul(self, scale: f64) -> Point {
-        Point::new(self.x * scale, self.y * scale, self.z * scale)
+    pub fn from_color(color: Color) -> HdrColor {
+        HdrColor::new(
+            color.r as f64 / 255.0,
+            color.g as f64 / 255.0,
+            color.b as f64 / 255.0,
+        )
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub struct Color {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
+impl fmt::Display for HdrColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HdrColor(r={}, g={}, b={})", self.r, self.g, self.b)
+    }
 }
 
-impl Color {
-    pub fn new(r: u8, g: u8, b: u8) -> Color {
-        Color { r: r, g: g, b: b }
+impl Add for HdrColor {
+    type Output = HdrColor;
+
+    fn add(self, other: HdrColor) -> HdrColor {
+        HdrColor::new(self.r + other.r, self.g + other.g, self.b + other.b)
     }
 }
 
-impl fmt::Display for Color {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Color(r={}, g={}, b={})", self.r, self.g, self.b)
+impl Mul<f64> for HdrColor {
+    type Output = HdrColor;
+
+    fn mul(self, scale: f64) -> HdrColor {
+        HdrColor::new(self.r * scale, self.g * scale, self.b * scale)
     }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct ColoredPoint {
-    pub point: Point,
+    pub point: Point3,
     pub color: Color,
+    // points outwards of the surface
+    pub normal: Normal3,
+    // normal used for shading, differs from the geometric one on bumpy surfaces
+    pub shading_normal: Normal3,
+    // assigned by the scene, 0 is the sky
+    pub object_id: usize,
 }
 
 impl PointInSpace for ColoredPoint {
@@ -121,99 +171,306 @@ impl PointInSpace for ColoredPoint {
 }
 
 impl ColoredPoint {
-    pub fn new(point: Point, color: Color) -> ColoredPoint {
-        ColoredPoint { point: point, color: color }
+    pub fn new(point: Point3, color: Color, normal: Normal3) -> ColoredPoint {
+        ColoredPoint { point, color, normal, shading_normal: normal, object_id: 0 }
+    }
+}
+
+// everything the eye sees through a point of the screen
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PixelSample {
+    pub radiance: HdrColor,
+    // length of the whole way from the eye including mirror reflections, infinite for the sky
+    pub depth: f64,
+    // shading normal, zero for the sky
+    pub normal: Vec3,
+    pub albedo: HdrColor,
+    pub object_id: usize,
+    pub in_shadow: bool,
+}
+
+impl PixelSample {
+    pub fn sky(color: HdrColor) -> PixelSample {
+        PixelSample {
+            radiance: color,
+            depth: f64::INFINITY,
+            normal: Vec3::zero(),
+            albedo: color,
+            object_id: 0,
+            in_shadow: false,
+        }
     }
 }
 
 #[derive(Clone, Copy)]
 pub struct Ray {
-    pub start: Point,
-    pub direction: Point,
+    pub start: Point3,
+    pub direction: Vec3,
+    // how fast the pixel footprint grows with the distance, 0.0 when unknown
+    pub spread: f64,
+    // moving objects are where they are at this time, rays made from this one keep it
+    pub time: f64,
 }
 
 impl Ray {
-    pub fn new(start: Point, direction: Point) -> Ray {
-        Ray { start: start, direction: direction }
+    pub fn new(start: Point3, direction: Vec3) -> Ray {
+        Ray { start, direction, spread: 0.0, time: 0.0 }
     }
 
-    pub fn from_to(start: Point, end: Point) -> Ray {
+    // approximate size of the pixel on a surface hit by the ray
+    pub fn get_footprint(&self, point: Point3, normal: Normal3) -> f64 {
+        let length = self.direction.length();
+        if self.spread == 0.0 || length == 0.0 {
+            return 0.0;
+        }
+        let cos = self.direction.dot(*normal) / length;
+        // grazing angles would make the footprint infinite
+        get_distance(self.start, point) * self.spread / cos.abs().max(0.05)
+    }
+
+    pub fn from_to(start: Point3, end: Point3) -> Ray {
         Ray::new(start, end - start)
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Sphere {
-    pub center: Point,
+    pub center: Point3,
     pub radius: f64,
     pub color: Color,
     pub is_mirror: bool,
+    // replaces color when present
+    pub texture: Option<Rc<dyn Texture>>,
+    pub bump: Option<BumpMap>,
 }
 
 pub struct Scene {
-    pub floor: Floor,
-    pub light_source: Point,
-    pub sky_color: Color,
+    // a scene doesn't need a floor, more planes can go to objects
+    pub floor: Option<Floor>,
+    pub light_source: Point3,
+    // seen by rays that escape, also in mirrors
+    pub background: Rc<dyn Background>,
     pub spheres: Vec<Sphere>,
-    pub eye: Point,
+    // neither mirrors nor limited to spheres, e.g. transformed instances
+    pub objects: Vec<Rc<dyn Shape>>,
+    pub eye: Point3,
+    pub camera: Camera,
+    pub tone_mapping: ToneMapping,
+    // in stops, 1.0 doubles the radiance
+    pub exposure: f64,
 }
 
 impl Scene {
     pub fn color_at(&self, y: u32, z: u32) -> Color {
-        let point_at_screen = Point::new(0.0, y as f64, z as f64);
-        let ray = Ray::from_to(self.eye, point_at_screen);
-        let (points, cur_ray) = self.get_all_colored_intersections(ray, true);
+        self.tone_mapping.apply(self.radiance_at(y, z), self.exposure)
+    }
+
+    pub fn render(&self, framebuffer: &mut Framebuffer) {
+        self.render_with_aovs(framebuffer, &[]);
+    }
+
+    // every aov goes to the framebuffer layer with its name
+    // screen y grows to the left of the image and z grows to its top
+    pub fn render_with_aovs(&self, framebuffer: &mut Framebuffer, aovs: &[Aov]) {
+        let region = Region::full(framebuffer.get_width(), framebuffer.get_height());
+        self.render_region_with_aovs(framebuffer, aovs, region);
+    }
+
+    // pixels outside of the region stay black
+    pub fn render_region_with_aovs(&self, framebuffer: &mut Framebuffer, aovs: &[Aov], region: Region) {
+        for aov in aovs {
+            framebuffer.add_layer(aov.get_name());
+        }
+        let width = framebuffer.get_width();
+        let height = framebuffer.get_height();
+        for row in region.top..region.bottom {
+            for column in region.left..region.right {
+                let sample = self.sample_at(width - 1 - column, height - 1 - row);
+                framebuffer.set_pixel(column, row, sample.radiance);
+                for aov in aovs {
+                    let layer = framebuffer.get_layer_mut(aov.get_name()).unwrap();
+                    layer.set_pixel(column, row, aov.get_value(&sample));
+                }
+            }
+        }
+    }
+
+    // one more sample for every pixel of the rows, the aovs come from the first one
+    pub fn add_samples(&self, accumulation: &mut Accumulation, rows: Range<u32>) {
+        let region = Region::new(0, rows.start, accumulation.get_width(), rows.end);
+        self.add_samples_in_region(accumulation, region);
+    }
+
+    pub fn add_samples_in_region(&self, accumulation: &mut Accumulation, region: Region) {
+        let width = accumulation.get_width();
+        let height = accumulation.get_height();
+        for row in region.top..region.bottom {
+            for column in region.left..region.right {
+                let index = accumulation.get_sample_count(column, row);
+                let sample = self.sample_progressively_at(width - 1 - column, height - 1 - row, index);
+                accumulation.add_sample(column, row, sample.radiance);
+                if index > 0 {
+                    continue;
+                }
+                for layer in accumulation.get_aovs_mut().get_layers_mut() {
+                    let aov = Aov::from_name(&layer.name).unwrap();
+                    layer.set_pixel(column, row, aov.get_value(&sample));
+                }
+            }
+        }
+    }
+
+    // every sample of a pixel has its own random numbers, so samples added later continue the sequence,
+    // the first one goes through the middle of the pixel like a render with one sample
+    fn sample_progressively_at(&self, y: u32, z: u32, index: u32) -> PixelSample {
+        let seed = Rng::with_stream(self.camera.seed, index as u64).next_u64();
+        let mut rng = Rng::with_stream(seed, (y as u64) << 32 | z as u64);
+        let (dy, dz) = if index > 0 { (rng.next_f64() - 0.5, rng.next_f64() - 0.5) } else { (0.0, 0.0) };
+        self.trace_screen_point(y as f64 + dy, z as f64 + dz, &mut rng)
+    }
+
+    pub fn radiance_at(&self, y: u32, z: u32) -> HdrColor {
+        self.sample_at(y, z).radiance
+    }
+
+    // radiance is averaged over the samples of the camera, the other values come from the first one
+    pub fn sample_at(&self, y: u32, z: u32) -> PixelSample {
+        let (y, z) = (y as f64, z as f64);
+        let mut rng = Rng::with_stream(self.camera.seed, (y as u64) << 32 | z as u64);
+        let samples = self.camera.samples.max(1);
+        let mut result: Option<PixelSample> = None;
+        let mut radiance = HdrColor::new(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            let (dy, dz) = if samples > 1 { (rng.next_f64() - 0.5, rng.next_f64() - 0.5) } else { (0.0, 0.0) };
+            let sample = self.trace_screen_point(y + dy, z + dz, &mut rng);
+            radiance = radiance + sample.radiance;
+            result.get_or_insert(sample);
+        }
+        PixelSample { radiance: radiance * (1.0 / samples as f64), ..result.unwrap() }
+    }
+
+    fn trace_screen_point(&self, y: f64, z: f64, rng: &mut Rng) -> PixelSample {
+        match self.camera.get_pinhole_ray(self.eye, y, z) {
+            Some(ray) => {
+                let time = self.camera.sample_time(rng);
+                let mut ray = self.camera.get_lens_ray(ray, rng);
+                ray.start = ray.start + self.camera.eye_motion * (time - self.camera.shutter_open);
+                ray.time = time;
+                self.trace(ray)
+            }
+            // e.g. the corners around a fisheye image
+            None => PixelSample::sky(HdrColor::new(0.0, 0.0, 0.0)),
+        }
+    }
+
+    fn trace(&self, ray: Ray) -> PixelSample {
+        let (points, cur_ray, travelled) = self.get_all_colored_intersections(ray, true);
         let closest_point = get_closest_point(cur_ray.start, &points);
-        let lighted_point = closest_point.map(|p| self.apply_lightning(p));
-        match lighted_point {
-            Some(point) => point.color,
-            None => self.sky_color,
+        match closest_point {
+            Some(point) => {
+                let (radiance, in_shadow) = self.apply_lightning(point, ray.time);
+                PixelSample {
+                    radiance,
+                    depth: travelled + get_distance(cur_ray.start, point),
+                    normal: point.shading_normal.to_vec(),
+                    albedo: HdrColor::from_color(point.color),
+                    object_id: point.object_id,
+                    in_shadow,
+                }
+            }
+            None => PixelSample::sky(self.background.radiance_at(cur_ray.direction)),
         }
     }
 
-    fn apply_lightning(&self, point: ColoredPoint) -> ColoredPoint {
-        let ray_to_light = Ray::from_to(point.point, self.light_source);
-        let (points, _) = self.get_all_colored_intersections(ray_to_light, false);
+    // returns radiance and whether the point is in shadow
+    fn apply_lightning(&self, point: ColoredPoint, time: f64) -> (HdrColor, bool) {
+        let ray_to_light = Ray { time, ..Ray::from_to(point.point, self.light_source) };
+        let (points, _, _) = self.get_all_colored_intersections(ray_to_light, false);
         let obstacle_point = get_closest_point(point, &exclude_close_points(point, &points));
         let coeff = match obstacle_point {
             Some(_) => 3.0,  // shadow
             None => 1.0,
         };
         let distance_to_light = get_distance(point, self.light_source) * coeff;
-        ColoredPoint::new(
-            point.point,
-            intensify(point.color, get_brightness(distance_to_light)),
-        )
+        let bump = bump::get_lighting_factor(point.shading_normal, point.normal, self.light_source - point.point);
+        let radiance = HdrColor::from_color(point.color) * (get_brightness(distance_to_light) * bump);
+        (radiance, obstacle_point.is_some())
     }
 
-    fn get_all_colored_intersections(&self, ray: Ray, with_mirroring: bool) -> (Vec<ColoredPoint>, Ray) {
+    // also returns the ray after mirror reflections and the distance travelled to its start
+    fn get_all_colored_intersections(&self, ray: Ray, with_mirroring: bool) -> (Vec<ColoredPoint>, Ray, f64) {
         let mut cur_ray = ray;
+        let mut travelled = 0.0;
         let mut num_iter = 0;
         if with_mirroring {
             num_iter = 3;
         }
-        let floor_points = get_closest_point(ray.start, &self.floor.get_colored_intersections(ray));
+        let floor_points = get_closest_point(ray.start, &self.get_floor_intersections(ray));
         let sphere_points = get_closest_point(ray.start, &self.get_sphere_intersections(ray));
-        if with_mirroring & floor_points.is_some() & !sphere_points.is_some() {
-            return (vec![floor_points.unwrap()], ray);
+        let object_points = get_closest_point(ray.start, &self.get_object_intersections(ray));
+        if let (true, Some(floor_point), None, None) = (with_mirroring, floor_points, sphere_points, object_points) {
+            return (vec![floor_point], ray, travelled);
         }
         for _ in 1..(num_iter + 1) {
             for sphere in self.spheres.iter() {
-                cur_ray = get_refraction_from_sphere(cur_ray, *sphere).unwrap_or(cur_ray);
+                if let Some(next_ray) = get_refraction_from_sphere(cur_ray, sphere) {
+                    if self.is_hidden_by_objects(cur_ray, next_ray.start) {
+                        continue;
+                    }
+                    travelled += get_distance(cur_ray.start, next_ray.start);
+                    cur_ray = next_ray;
+                }
             }
         }
-        let mut points = self.floor.get_colored_intersections(cur_ray);
+        let mut points = self.get_floor_intersections(cur_ray);
         points.extend(self.get_sphere_intersections(cur_ray));
-        (points, cur_ray)
+        points.extend(self.get_object_intersections(cur_ray));
+        // reflected rays start on the mirror and would hit it again
+        (exclude_close_points(cur_ray.start, &points), cur_ray, travelled)
     }
 
+    fn get_floor_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        let mut points = match self.floor {
+            Some(ref floor) => floor.get_colored_intersections(ray),
+            None => vec![],
+        };
+        for point in points.iter_mut() {
+            point.object_id = FLOOR_OBJECT_ID;
+        }
+        points
+    }
+
+    // spheres get object ids after the floor, in their order
     fn get_sphere_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
         let mut points = vec![];
-        for sphere in self.spheres.iter() {
-            points.extend(sphere.get_colored_intersections(ray));
+        for (i, sphere) in self.spheres.iter().enumerate() {
+            for mut point in sphere.get_colored_intersections(ray) {
+                point.object_id = FLOOR_OBJECT_ID + 1 + i;
+                points.push(point);
+            }
+        }
+        points
+    }
+
+    // objects get ids after the spheres
+    fn get_object_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        let mut points = vec![];
+        for (i, object) in self.objects.iter().enumerate() {
+            for mut point in object.get_colored_intersections(ray) {
+                point.object_id = FLOOR_OBJECT_ID + 1 + self.spheres.len() + i;
+                points.push(point);
+            }
         }
         points
     }
+
+    // objects in front of a mirror hide it
+    fn is_hidden_by_objects(&self, ray: Ray, point: Point3) -> bool {
+        let distance = get_distance(ray.start, point);
+        exclude_close_points(ray.start, &self.get_object_intersections(ray))
+            .iter()
+            .any(|object_point| get_distance(ray.start, *object_point) < distance)
+    }
 }
 
 fn exclude_close_points<S, T>(point: S, points: &Vec<T>) -> Vec<T> where S: PointInSpace + Copy, T: PointInSpace + Copy {
@@ -227,30 +484,61 @@ fn exclude_close_points<S, T>(point: S, points: &Vec<T>) -> Vec<T> where S: Poin
 }
 
 impl Sphere {
-    pub fn get_intersections(&self, ray: Ray) -> Vec<Point> {
+    pub fn get_intersections(&self, ray: Ray) -> Vec<Point3> {
         let p = ray.start - self.center;
-        let a = ray.direction.x.powi(2) + ray.direction.y.powi(2) + ray.direction.z.powi(2);
-        let b = 2.0 * (p.x * ray.direction.x + p.y * ray.direction.y + p.z * ray.direction.z);
-        let c = p.x.powi(2) + p.y.powi(2) + p.z.powi(2) - self.radius.powi(2);
+        let a = ray.direction.length_squared();
+        let b = 2.0 * p.dot(ray.direction);
+        let c = p.length_squared() - self.radius.powi(2);
         let roots = get_quadratic_equation_roots(a, b, c);
         let mut points = vec![];
         for root in roots {
             if root >= 0.0 {
-                let point = Point::new(
-                    ray.start.x + root * ray.direction.x,
-                    ray.start.y + root * ray.direction.y,
-                    ray.start.z + root * ray.direction.z,
-                );
-                points.push(point)
+                points.push(ray.start + ray.direction * root)
             }
         }
-        return points;
+        points
+    }
+
+    // u goes around the vertical axis and v from the bottom to the top
+    pub fn color_at(&self, point: Point3) -> Color {
+        self.get_color(self.get_texture_coordinates(point, 0.0))
+    }
+
+    fn get_color(&self, coordinates: TextureCoordinates) -> Color {
+        match self.texture {
+            Some(ref texture) => texture.color_at(coordinates),
+            None => self.color,
+        }
     }
 
-    pub fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+    // footprint is in world units
+    fn get_texture_coordinates(&self, point: Point3, footprint: f64) -> TextureCoordinates {
+        let (u, v) = texture::get_spherical_coordinates(point, self.center);
+        let mut coordinates = TextureCoordinates::new(point, u, v);
+        // u goes around the whole equator
+        coordinates.footprint = footprint / (2.0 * PI * self.radius);
+        coordinates
+    }
+}
+
+impl Shape for Sphere {
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
         let mut points = vec![];
         for point in self.get_intersections(ray) {
-            let colored_point = ColoredPoint::new(point, self.color);
+            // a sphere shrunk to a point has no surface to hit
+            let normal = match Normal3::try_new(point - self.center) {
+                Some(normal) => normal,
+                None => continue,
+            };
+            let coordinates = self.get_texture_coordinates(point, ray.get_footprint(point, normal));
+            let mut colored_point = ColoredPoint::new(point, self.get_color(coordinates), normal);
+            if let Some(ref bump) = self.bump {
+                // u grows counterclockwise around the vertical axis
+                let tangent = Vec3::new(self.center.y - point.y, point.x - self.center.x, 0.0);
+                let mut frame = TangentFrame::new(tangent, normal);
+                frame.uv_lengths = (2.0 * PI * tangent.length(), PI * self.radius);
+                colored_point.shading_normal = bump.get_normal(coordinates, &frame);
+            }
             points.push(colored_point);
         }
         points
@@ -265,73 +553,151 @@ pub struct Plane {
 }
 
 impl Plane {
-    pub fn new(a: f64, b: f64, c: f64, d: f64) -> Plane {
-        Plane { a: a, b: b, c: c, d: d }
+    // a * x + b * y + c * z + d = 0, None when a, b and c aren't a normal, e.g. all zero
+    pub fn new(a: f64, b: f64, c: f64, d: f64) -> Option<Plane> {
+        Normal3::try_new(Vec3::new(a, b, c))?;
+        Some(Plane { a, b, c, d })
+    }
+
+    pub fn from_point_and_normal(point: Point3, normal: Normal3) -> Plane {
+        Plane { a: normal.x, b: normal.y, c: normal.z, d: -normal.dot(point.to_vec()) }
+    }
+
+    pub fn get_normal(&self) -> Normal3 {
+        Normal3::new(self.get_coefficients())
     }
 
-    pub fn get_intersections(&self, ray: Ray) -> Vec<Point> {
+    pub fn get_intersections(&self, ray: Ray) -> Vec<Point3> {
         let mut result = vec![];
-        let denominator = self.a * ray.direction.x + self.b * ray.direction.y + self.c * ray.direction.z;
+        let denominator = self.get_coefficients().dot(ray.direction);
         if !are_close(denominator, 0.0) {
-            let numerator = -(self.a * ray.start.x + self.b * ray.start.y + self.c * ray.start.z + self.d);
+            let numerator = -(self.get_coefficients().dot(ray.start.to_vec()) + self.d);
             let k = numerator / denominator;
             if k >= 0.0 {
-                let point = Point::new(
-                    ray.start.x + k * ray.direction.x,
-                    ray.start.y + k * ray.direction.y,
-                    ray.start.z + k * ray.direction.z,
-                );
-                result.push(point);
+                result.push(ray.start + ray.direction * k);
             }
         }
         result
     }
+
+    // a, b and c
+    fn get_coefficients(&self) -> Vec3 {
+        Vec3::new(self.a, self.b, self.c)
+    }
 }
 
 pub const WHITE: Color = Color { r: 200, g: 200, b: 200 };
 pub const BLACK: Color = Color { r: 50, g: 50, b: 50 };
 const EPSILON: f64 = 0.001;
+pub const FLOOR_OBJECT_ID: usize = 1;
 
 
+// textured plane, the z = 0 plane with u along x and v along y unless it's placed elsewhere
 pub struct Floor {
-    step: f64,
-    first_color: Color,
-    second_color: Color,
+    texture: Rc<dyn Texture>,
+    mapping: PlanarMapping,
+    bump: Option<BumpMap>,
+    origin: Point3,
+    // u goes along the tangent and v along the bitangent
+    frame: TangentFrame,
+    // along u and v, centered on the origin, infinite when None
+    size: Option<(f64, f64)>,
     plane: Plane,
 }
 
 impl Floor {
     pub fn new(step: f64, first_color: Color, second_color: Color) -> Floor {
+        Floor::with_texture(Rc::new(Checker { step, first_color, second_color }))
+    }
+
+    // texture u and v are floor x and y
+    pub fn with_texture(texture: Rc<dyn Texture>) -> Floor {
+        Floor::with_mapped_texture(texture, PlanarMapping::identity())
+    }
+
+    pub fn with_mapped_texture(texture: Rc<dyn Texture>, mapping: PlanarMapping) -> Floor {
+        let origin = Point3::origin();
+        let normal = Normal3::new(Vec3::new(0.0, 0.0, 1.0));
         Floor {
-            step: step,
-            first_color: first_color,
-            second_color: second_color,
-            plane: Plane { a: 0.0, b: 0.0, c: 1.0, d: 0.0 },
+            texture,
+            mapping,
+            bump: None,
+            origin,
+            frame: TangentFrame::new(Vec3::new(1.0, 0.0, 0.0), normal),
+            size: None,
+            plane: Plane::from_point_and_normal(origin, normal),
         }
     }
 
-    pub fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+    // uses the same mapping as the texture
+    pub fn set_bump_map(&mut self, bump: Option<BumpMap>) {
+        self.bump = bump;
+    }
+
+    // the plane goes through the origin, u follows the part of u_direction along it
+    pub fn set_placement(&mut self, origin: Point3, normal: Normal3, u_direction: Vec3) {
+        self.origin = origin;
+        self.frame = TangentFrame::new(u_direction, normal);
+        self.plane = Plane::from_point_and_normal(origin, normal);
+    }
+
+    // turns the plane into a rectangle of the given width along u and height along v
+    pub fn set_size(&mut self, size: Option<(f64, f64)>) {
+        self.size = size;
+    }
+
+    // points off the plane get the color of their projection onto it
+    pub fn color_at(&self, point: Point3) -> Color {
+        self.texture.color_at(self.get_texture_coordinates(point, 0.0))
+    }
+
+    // distances from the origin along u and v
+    fn get_plane_coordinates(&self, point: Point3) -> (f64, f64) {
+        let offset = point - self.origin;
+        (offset.dot(self.frame.tangent), offset.dot(self.frame.bitangent))
+    }
+
+    fn contains(&self, point: Point3) -> bool {
+        match self.size {
+            Some((width, height)) => {
+                let (x, y) = self.get_plane_coordinates(point);
+                x.abs() <= width / 2.0 && y.abs() <= height / 2.0
+            }
+            None => true,
+        }
+    }
+
+    // footprint is in world units
+    fn get_texture_coordinates(&self, point: Point3, footprint: f64) -> TextureCoordinates {
+        let (x, y) = self.get_plane_coordinates(point);
+        let (u, v) = self.mapping.apply(x, y);
+        let mut coordinates = TextureCoordinates::new(point, u, v);
+        coordinates.footprint = self.mapping.get_footprint(footprint);
+        coordinates
+    }
+}
+
+impl Shape for Floor {
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
         let mut result = vec![];
         for point in self.plane.get_intersections(ray) {
-            let color = self.color_at(point);
-            let colored_point = ColoredPoint::new(point, color);
+            if !self.contains(point) {
+                continue;
+            }
+            let normal = self.frame.normal;
+            let coordinates = self.get_texture_coordinates(point, ray.get_footprint(point, normal));
+            let mut colored_point = ColoredPoint::new(point, self.texture.color_at(coordinates), normal);
+            if let Some(ref bump) = self.bump {
+                // u grows along the rotated tangent
+                let tangent = self.frame.to_world(self.mapping.rotation.cos(), -self.mapping.rotation.sin(), 0.0);
+                let mut frame = TangentFrame::new(tangent, normal);
+                frame.uv_lengths = (1.0 / self.mapping.scale_u.abs(), 1.0 / self.mapping.scale_v.abs());
+                colored_point.shading_normal = bump.get_normal(coordinates, &frame);
+            }
             result.push(colored_point);
         }
         result
     }
-
-    pub fn color_at(&self, point: Point) -> Color {
-        if !are_close(point.z, 0.0) {
-            panic!("{} is not close to 0.0", point.z);
-        }
-        let x = (point.x / self.step).floor().abs() as i32;
-        let y = (point.y / self.step).floor().abs() as i32;
-        if (x % 2) == (y % 2) {
-            self.first_color
-        } else {
-            self.second_color
-        }
-    }
 }
 
 pub fn are_close(a: f64, b: f64) -> bool {
@@ -363,9 +729,8 @@ pub fn are_close_points<S, T>(a: T, b: S) -> bool where S: PointInSpace, T: Poin
 pub fn get_quadratic_equation_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
     if a == 0.0 {
         if b == 0.0 {
-            // TODO: wtf?
+            // not an equation
             return vec![];
-            panic!("not an equation");
         }
         return vec![-c / b];
     }
@@ -374,44 +739,99 @@ pub fn get_quadratic_equation_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
         return vec![];
     }
     let d_sqrt = d.sqrt();
-    let mut result = vec![];
-    result.push((-b + d_sqrt) / (2.0 * a));
-    result.push((-b - d_sqrt) / (2.0 * a));
-    result
+    vec![(-b + d_sqrt) / (2.0 * a), (-b - d_sqrt) / (2.0 * a)]
+}
+
+// real roots only, in no particular order
+pub fn get_cubic_equation_roots(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return get_quadratic_equation_roots(b, c, d);
+    }
+    let (b, c, d) = (b / a, c / a, d / a);
+    // x = t - b / 3 gives t^3 + p * t + q = 0
+    let p = c - b * b / 3.0;
+    let q = 2.0 * b.powi(3) / 27.0 - b * c / 3.0 + d;
+    let shift = -b / 3.0;
+    let discriminant = (q / 2.0).powi(2) + (p / 3.0).powi(3);
+    if discriminant > 0.0 {
+        let d_sqrt = discriminant.sqrt();
+        return vec![(-q / 2.0 + d_sqrt).cbrt() + (-q / 2.0 - d_sqrt).cbrt() + shift];
+    }
+    if p == 0.0 {
+        return vec![shift];
+    }
+    // three real roots
+    let radius = 2.0 * (-p / 3.0).sqrt();
+    let angle = ((3.0 * q / (2.0 * p)) * (-3.0 / p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+    (0..3).map(|k| radius * (angle - 2.0 * PI * k as f64 / 3.0).cos() + shift).collect()
 }
 
-pub fn get_perpendicular_ray(point: Point, ray: Ray) -> Option<Ray> {
-    let numerator = (point.x - ray.start.x) * ray.direction.x + (point.y - ray.start.y) * ray.direction.y + (point.z - ray.start.z) * ray.direction.z;
-    let denominator = ray.direction.x.powi(2) + ray.direction.y.powi(2) + ray.direction.z.powi(2);
+// real roots only, in no particular order, ferrari's method polished with newton's
+pub fn get_quartic_equation_roots(a: f64, b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return get_cubic_equation_roots(b, c, d, e);
+    }
+    let (b, c, d, e) = (b / a, c / a, d / a, e / a);
+    // x = y - b / 4 gives y^4 + p * y^2 + q * y + r = 0
+    let p = c - 3.0 * b * b / 8.0;
+    let q = d - b * c / 2.0 + b.powi(3) / 8.0;
+    let r = e - b * d / 4.0 + b * b * c / 16.0 - 3.0 * b.powi(4) / 256.0;
+    let mut roots = vec![];
+    if q.abs() < 1e-12 {
+        // biquadratic
+        for z in get_quadratic_equation_roots(1.0, p, r) {
+            if z >= 0.0 {
+                roots.push(z.sqrt());
+                roots.push(-z.sqrt());
+            }
+        }
+    } else {
+        // the resolvent cubic has a positive root because it's negative at zero
+        let m = get_cubic_equation_roots(8.0, 8.0 * p, 2.0 * p * p - 8.0 * r, -q * q)
+            .into_iter()
+            .fold(f64::NEG_INFINITY, f64::max);
+        if m <= 0.0 {
+            return vec![];
+        }
+        let s = (2.0 * m).sqrt();
+        roots.extend(get_quadratic_equation_roots(1.0, -s, p / 2.0 + m + s * q / (4.0 * m)));
+        roots.extend(get_quadratic_equation_roots(1.0, s, p / 2.0 + m - s * q / (4.0 * m)));
+    }
+    let value_at = |x: f64| (((x + b) * x + c) * x + d) * x + e;
+    let slope_at = |x: f64| ((4.0 * x + 3.0 * b) * x + 2.0 * c) * x + d;
+    roots.into_iter().map(|y| {
+        let mut x = y - b / 4.0;
+        for _ in 0..2 {
+            let slope = slope_at(x);
+            if slope != 0.0 {
+                x -= value_at(x) / slope;
+            }
+        }
+        x
+    }).collect()
+}
+
+pub fn get_perpendicular_ray(point: Point3, ray: Ray) -> Option<Ray> {
+    let denominator = ray.direction.length_squared();
     if are_close(denominator, 0.0) {
         return None
     }
-    let k = numerator / denominator;
+    let k = (point - ray.start).dot(ray.direction) / denominator;
     if k < 0.0 {
         return None
     }
-    //    panic!("intersection inter = {}, perp.start = {}, perp.direction = {}, k = {}", point, ray.start, ray.direction, k);
-    let point_on_ray = Point::new(
-        ray.start.x + k * ray.direction.x,
-        ray.start.y + k * ray.direction.y,
-        ray.start.z + k * ray.direction.z,
-    );
-    Some(Ray::from_to(point, point_on_ray))
+    Some(Ray::from_to(point, ray.start + ray.direction * k))
 }
 
-pub fn get_refraction_from_sphere(ray: Ray, sphere: Sphere) -> Option<Ray> {
-    let intersections = sphere.get_intersections(ray);
-    let closest_point = get_closest_point(ray.start, &intersections);
-    if !closest_point.is_some() {
-        return None
-    }
-    let point = closest_point.unwrap();
+pub fn get_refraction_from_sphere(ray: Ray, sphere: &Sphere) -> Option<Ray> {
+    // a ray reflected by the sphere starts on it
+    let intersections = exclude_close_points(ray.start, &sphere.get_intersections(ray));
+    let point = get_closest_point(ray.start, &intersections)?;
+    // reflections keep the spread and the time of the incoming ray, the spread ignores the curvature of the mirror
     if !sphere.is_mirror {
-        return Some(Ray::from_to(ray.start, point));
+        return Some(Ray { spread: ray.spread, time: ray.time, ..Ray::from_to(ray.start, point) });
     }
-    let perpendicular_ray = get_perpendicular_ray(ray.start, Ray::from_to(sphere.center, point));
-    perpendicular_ray
-        .map(|r| r.start + r.direction * 2.0)
-        .map(|p| Ray::from_to(point, p))
+    let direction = ray.direction.reflect(Normal3::try_new(point - sphere.center)?);
+    Some(Ray { spread: ray.spread, time: ray.time, ..Ray::new(point, direction) })
 }
 
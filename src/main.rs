@@ -1,70 +1,541 @@
-extern crate bmp;
+#[macro_use]
 extern crate clap;
 extern crate raytracer;
 
-use bmp::{Image, Pixel};
-use raytracer::{Scene, Sphere, Point, Color, Floor};
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use clap::{Arg, ArgMatches};
+use raytracer::{Aov, Background, Camera, Color, Floor, Framebuffer, HdrColor, Keyframe, Point3, Projection, Region, Scene, SceneAnimation, Sphere, ToneMapping, Track, Vec3};
+use raytracer::animation::SphereAnimation;
+use raytracer::background::{EnvironmentMap, Uniform};
+use raytracer::denoise::{self, DenoiseOptions};
+use raytracer::input;
+use raytracer::output::{self, ExrPrecision, ImageFormat};
+use raytracer::progressive::Accumulation;
 
 
 fn main() {
-    let size = 800;
-    let scene = make_scene(size);
-    let mut image = Image::new(size, size);
-
-    for y in 0..size {
-        for z in 0..size {
-            let color = scene.color_at(y, z);
-            image.set_pixel(size - 1 - y, size - 1 - z, color_to_pixel(color));
-        }
-    }
     let matches = clap::App::new("raytracer")
         .version("0.1.0")
         .args_from_usage(
-            "<OUTPUT_PATH> 'output .bmp file path'"
-        ).get_matches();
+            "<OUTPUT_PATH> 'output image path, .bmp, .png, .ppm, .hdr, .exr or .pfm, - for stdout'
+            --white-point=[RADIANCE] 'radiance that maps to white with extended-reinhard tone mapping'
+            --exposure=[STOPS] 'exposure adjustment in stops'
+            --denoise 'smooth the image guided by depth, normal and albedo'
+            --environment=[PATH] 'equirectangular image used as the sky, .exr, .hdr, .pfm, .png, .bmp or .ppm'
+            --aperture=[RADIUS] 'radius of the lens, blurs what is out of focus'
+            --focus-distance=[DISTANCE] 'distance from the eye that stays sharp, the spheres by default'
+            --blades=[COUNT] 'polygonal aperture with this many blades instead of a round one'
+            --samples=[COUNT] 'rays averaged for every pixel'
+            --seed=[SEED] 'seed of the random lens and pixel positions'
+            --frames=[FIRST..LAST] 'renders the animation into numbered images, a run of # in OUTPUT_PATH becomes the frame number'
+            --fps=[RATE] 'frames in one second of the animation'
+            --shutter=[FRACTION] 'part of a frame the shutter stays open, moving things blur with enough samples'
+            --skip-existing 'keeps frames that are already rendered'
+            --progressive 'adds --samples passes over the whole image one after another'
+            --time-limit=[SECONDS] 'stops adding passes when the time is up, keeps going until then without --samples'
+            --checkpoint-seconds=[SECONDS] 'saves the image so far this often while rendering progressively'
+            --checkpoint-passes=[COUNT] 'saves the image so far after this many passes'
+            --checkpoint=[PATH] 'also saves the samples so far at the checkpoints and the end to continue later, numbered like the frames'
+            --crop 'saves only the --region instead of the whole image with the rest black'"
+        )
+        .arg(Arg::from_usage("--region=[LEFT,TOP,RIGHT,BOTTOM] 'renders only these pixels from the top left corner, without the right and bottom ones'")
+            .conflicts_with("tiles"))
+        .arg(Arg::from_usage("--tiles=[INDEX/COUNT] 'renders only band INDEX of COUNT from the top, counting from 1, cropped for merge'"))
+        .subcommand(clap::SubCommand::with_name("merge")
+            .about("stacks bands rendered with --tiles into one image")
+            .args_from_usage(
+                "<OUTPUT_PATH> 'merged image path, .bmp, .png, .ppm, .hdr, .exr or .pfm'
+                <TILE_PATHS>... 'bands from the top one to the bottom one, .bmp, .png, .ppm, .hdr or .pfm'"
+            ))
+        .setting(clap::AppSettings::SubcommandsNegateReqs)
+        .arg(Arg::from_usage("--resume 'continues from the samples in --checkpoint when it exists, up to --samples in total'")
+            .requires("checkpoint"))
+        .arg(Arg::from_usage("--tone-mapping=[OPERATOR] 'tone mapping operator'")
+            .possible_values(&raytracer::tonemap::TONE_MAPPING_NAMES))
+        .arg(Arg::from_usage("--projection=[PROJECTION] 'how the eye sees the scene, fisheye and equirectangular see around it'")
+            .possible_values(&raytracer::camera::PROJECTION_NAMES))
+        .arg(Arg::from_usage("--format=[FORMAT] 'output image format, guessed from OUTPUT_PATH by default'")
+            .possible_values(&output::IMAGE_FORMAT_NAMES))
+        .arg(Arg::from_usage("--exr-precision=[PRECISION] 'channel type of .exr images'")
+            .possible_values(&["half", "float"]))
+        .arg(Arg::from_usage("--aov=[AOV]... 'extra images, layers of .exr or separate OUTPUT_PATH.AOV.EXTENSION files'")
+            .possible_values(&raytracer::aov::AOV_NAMES)
+            .use_delimiter(true))
+        .get_matches();
+    if let Some(merge_matches) = matches.subcommand_matches("merge") {
+        merge_tiles(merge_matches);
+        return;
+    }
+    let path = matches.value_of("OUTPUT_PATH").unwrap();
+    let format = get_image_format(&matches, path);
+    // panoramas are wider than high
+    let projection = matches.value_of("projection").map_or(Projection::Perspective, |name| Projection::from_name(name).unwrap());
+    let size = (projection.get_image_width(800), 800);
+    let environment = matches.value_of("environment").map(|environment_path| {
+        let environment = EnvironmentMap::open(environment_path).unwrap_or_else(|e| {
+            clap::Error::with_description(
+                &format!("can't load {}: {}", environment_path, e),
+                clap::ErrorKind::InvalidValue,
+            ).exit()
+        });
+        Rc::new(environment) as Rc<dyn Background>
+    });
+    let frames = matches.value_of("frames").map(|frames| parse_frames(frames).unwrap_or_else(|| {
+        clap::Error::with_description(
+            &format!("frames should look like 1..48, not {}", frames),
+            clap::ErrorKind::InvalidValue,
+        ).exit()
+    }));
+    let (first, last) = match frames {
+        Some(frames) => frames,
+        None => {
+            let mut scene = make_scene(size);
+            configure_scene(&matches, &mut scene, &environment);
+            render_and_save(&matches, &mut scene, size, path, format, matches.value_of("checkpoint"));
+            return;
+        }
+    };
+    if path == output::STDOUT_PATH {
+        clap::Error::with_description(
+            "frames can't be written to stdout, use a file path",
+            clap::ErrorKind::InvalidValue,
+        ).exit()
+    }
+    let frame_rate = get_f64_arg(&matches, "fps", 24.0);
+    let shutter = get_f64_arg(&matches, "shutter", 0.0);
+    if !(frame_rate.is_finite() && frame_rate > 0.0) {
+        clap::Error::with_description(
+            &format!("--fps should be a positive number, not {}", frame_rate),
+            clap::ErrorKind::InvalidValue,
+        ).exit()
+    }
+    if !(shutter.is_finite() && shutter >= 0.0) {
+        clap::Error::with_description(
+            &format!("--shutter should be a fraction of a frame, not {}", shutter),
+            clap::ErrorKind::InvalidValue,
+        ).exit()
+    }
+    let animation = make_animation(size);
+    for frame in first..=last {
+        let frame_path = get_frame_path(path, frame, last);
+        if matches.is_present("skip-existing") && Path::new(&frame_path).exists() {
+            continue;
+        }
+        let mut scene = make_scene(size);
+        configure_scene(&matches, &mut scene, &environment);
+        // the shutter stays open for this fraction of the frame
+        scene.camera.shutter_close = scene.camera.shutter_open + shutter / frame_rate;
+        animation.apply(&mut scene, frame as f64 / frame_rate).unwrap_or_else(|e| {
+            clap::Error::with_description(&format!("can't animate the scene: {}", e), clap::ErrorKind::InvalidValue).exit()
+        });
+        let checkpoint_path = matches.value_of("checkpoint").map(|checkpoint_path| get_frame_path(checkpoint_path, frame, last));
+        render_and_save(&matches, &mut scene, size, &frame_path, format, checkpoint_path.as_deref());
+    }
+}
+
+fn configure_scene(matches: &ArgMatches, scene: &mut Scene, environment: &Option<Rc<dyn Background>>) {
+    if let Some(name) = matches.value_of("tone-mapping") {
+        let white_point = get_f64_arg(matches, "white-point", 4.0);
+        scene.tone_mapping = ToneMapping::from_name(name, white_point).unwrap();
+    }
+    scene.exposure = get_f64_arg(matches, "exposure", scene.exposure);
+    if let Some(name) = matches.value_of("projection") {
+        scene.camera.projection = Projection::from_name(name).unwrap();
+    }
+    scene.camera.aperture_radius = get_f64_arg(matches, "aperture", scene.camera.aperture_radius);
+    scene.camera.focus_distance = get_f64_arg(matches, "focus-distance", scene.camera.focus_distance);
+    scene.camera.blades = get_optional_arg(matches, "blades").or(scene.camera.blades);
+    scene.camera.samples = get_optional_arg(matches, "samples").unwrap_or(scene.camera.samples);
+    scene.camera.seed = get_optional_arg(matches, "seed").unwrap_or(scene.camera.seed);
+    if let Some(ref environment) = *environment {
+        scene.background = environment.clone();
+    }
+}
+
+fn render_and_save(matches: &ArgMatches, scene: &mut Scene, size: (u32, u32), path: &str, format: ImageFormat, checkpoint_path: Option<&str>) {
+    let aovs: Vec<Aov> = matches.values_of("aov")
+        .map(|names| names.map(|name| Aov::from_name(name).unwrap()).collect())
+        .unwrap_or_default();
+    let mut aovs_with_guides = aovs.clone();
+    if matches.is_present("denoise") {
+        for guide in &[Aov::Depth, Aov::Normal, Aov::Albedo] {
+            if !aovs.contains(guide) {
+                aovs_with_guides.push(*guide);
+            }
+        }
+    }
+    let region = get_region(matches, size);
+    let mut framebuffer = if is_progressive(matches) {
+        render_progressively(matches, scene, size, &aovs_with_guides, path, format, checkpoint_path)
+    } else {
+        let mut framebuffer = Framebuffer::new(size.0, size.1);
+        scene.render_region_with_aovs(&mut framebuffer, &aovs_with_guides, region);
+        framebuffer
+    };
+    if is_cropped(matches) {
+        framebuffer = framebuffer.crop(region);
+    }
+    if matches.is_present("denoise") {
+        framebuffer = denoise::denoise(&framebuffer, &DenoiseOptions::default());
+        for guide in aovs_with_guides.iter().filter(|aov| !aovs.contains(aov)) {
+            framebuffer.remove_layer(guide.get_name());
+        }
+    }
+    check_saved(output::save_framebuffer(path, format, &framebuffer, scene.tone_mapping, scene.exposure));
+    if let ImageFormat::Exr(_) = format {
+        return;
+    }
+    for aov in &aovs {
+        save_aov(path, format, &framebuffer, *aov);
+    }
+}
+
+fn is_progressive(matches: &ArgMatches) -> bool {
+    ["progressive", "time-limit", "checkpoint-seconds", "checkpoint-passes", "checkpoint"].iter().any(|name| matches.is_present(name))
+}
+
+// passes over the whole image until --samples of them are done or the time is up,
+// the estimate so far goes to the output path at the checkpoints
+fn render_progressively(
+    matches: &ArgMatches,
+    scene: &mut Scene,
+    size: (u32, u32),
+    aovs: &[Aov],
+    path: &str,
+    format: ImageFormat,
+    checkpoint_path: Option<&str>,
+) -> Framebuffer {
+    // rows rendered between looks at the clock
+    const ROWS_AT_ONCE: u32 = 16;
+    let region = get_region(matches, size);
+    let time_limit = get_duration_arg(matches, "time-limit");
+    let passes: Option<u32> = match get_optional_arg(matches, "samples") {
+        Some(samples) => Some(samples),
+        None if time_limit.is_some() => None,
+        None => Some(scene.camera.samples.max(1)),
+    };
+    let checkpoint_interval = get_duration_arg(matches, "checkpoint-seconds");
+    let checkpoint_passes: Option<u32> = get_optional_arg(matches, "checkpoint-passes");
+    if path == output::STDOUT_PATH && (checkpoint_interval.is_some() || checkpoint_passes.is_some()) {
+        clap::Error::with_description(
+            "checkpoints can't be written to stdout, use a file path",
+            clap::ErrorKind::InvalidValue,
+        ).exit()
+    }
+    let (mut accumulation, seed) = match checkpoint_path {
+        Some(checkpoint_path) if matches.is_present("resume") && Path::new(checkpoint_path).exists() => {
+            resume_accumulation(checkpoint_path, size, aovs)
+        }
+        _ => (Accumulation::new(size.0, size.1, aovs), scene.camera.seed),
+    };
+    // samples added to a checkpoint continue its random numbers
+    scene.camera.seed = seed;
+    let scene = &*scene;
+    let save_checkpoint = |accumulation: &Accumulation| {
+        let mut framebuffer = accumulation.to_framebuffer();
+        if is_cropped(matches) {
+            framebuffer = framebuffer.crop(region);
+        }
+        check_saved(output::save_framebuffer(path, format, &framebuffer, scene.tone_mapping, scene.exposure));
+        if let Some(checkpoint_path) = checkpoint_path {
+            check_saved(accumulation.save(checkpoint_path, seed));
+        }
+    };
+    let start = Instant::now();
+    let mut last_checkpoint = start;
+    'passes: while passes.map_or(true, |passes| accumulation.get_passes_in(region) < passes) {
+        let mut row = region.top;
+        while row < region.bottom {
+            if time_limit.is_some_and(|limit| start.elapsed() >= limit) {
+                break 'passes;
+            }
+            let end = (row + ROWS_AT_ONCE).min(region.bottom);
+            scene.add_samples_in_region(&mut accumulation, Region::new(region.left, row, region.right, end));
+            row = end;
+            if checkpoint_interval.is_some_and(|interval| last_checkpoint.elapsed() >= interval) {
+                save_checkpoint(&accumulation);
+                last_checkpoint = Instant::now();
+            }
+        }
+        if checkpoint_passes.is_some_and(|every| every > 0 && accumulation.get_passes_in(region) % every == 0) {
+            save_checkpoint(&accumulation);
+            last_checkpoint = Instant::now();
+        }
+    }
+    if let Some(checkpoint_path) = checkpoint_path {
+        check_saved(accumulation.save(checkpoint_path, seed));
+    }
+    accumulation.to_framebuffer()
+}
+
+// the checkpoint has to be of the same image
+fn resume_accumulation(checkpoint_path: &str, size: (u32, u32), aovs: &[Aov]) -> (Accumulation, u64) {
+    let exit = |message: String| -> ! {
+        clap::Error::with_description(&message, clap::ErrorKind::InvalidValue).exit()
+    };
+    let (accumulation, seed) = Accumulation::open(checkpoint_path)
+        .unwrap_or_else(|e| exit(format!("can't resume from {}: {}", checkpoint_path, e)));
+    if (accumulation.get_width(), accumulation.get_height()) != size {
+        exit(format!("{} is a checkpoint of another image size", checkpoint_path));
+    }
+    let layers = accumulation.to_framebuffer();
+    if let Some(aov) = aovs.iter().find(|aov| layers.get_layer(aov.get_name()).is_none()) {
+        exit(format!("{} has no {} layer, render it again with --aov {}", checkpoint_path, aov.get_name(), aov.get_name()));
+    }
+    (accumulation, seed)
+}
+
+fn is_cropped(matches: &ArgMatches) -> bool {
+    matches.is_present("crop") || matches.is_present("tiles")
+}
+
+// the whole image without --region or --tiles
+fn get_region(matches: &ArgMatches, (width, height): (u32, u32)) -> Region {
+    let exit = |message: String| -> ! {
+        clap::Error::with_description(&message, clap::ErrorKind::InvalidValue).exit()
+    };
+    if let Some(tiles) = matches.value_of("tiles") {
+        let numbers: Vec<Option<u32>> = tiles.splitn(2, '/').map(|number| number.trim().parse().ok()).collect();
+        return match numbers[..] {
+            [Some(index), Some(count)] if index >= 1 && index <= count && count <= height => Region::tile(index, count, width, height),
+            _ => exit(format!("tiles should look like 2/4 with at most {} tiles, not {}", height, tiles)),
+        };
+    }
+    if let Some(region) = matches.value_of("region") {
+        let bounds: Vec<Option<u32>> = region.split(',').map(|bound| bound.trim().parse().ok()).collect();
+        return match bounds[..] {
+            [Some(left), Some(top), Some(right), Some(bottom)] if left < right && top < bottom && right <= width && bottom <= height => {
+                Region::new(left, top, right, bottom)
+            }
+            _ => exit(format!("region should look like 100,200,300,400 within the {}x{} image, not {}", width, height, region)),
+        };
+    }
+    Region::full(width, height)
+}
+
+// the tiles have to be as wide as each other
+fn merge_tiles(matches: &ArgMatches) {
+    let exit = |message: String| -> ! {
+        clap::Error::with_description(&message, clap::ErrorKind::InvalidValue).exit()
+    };
     let path = matches.value_of("OUTPUT_PATH").unwrap();
-    image.save(path).expect("couldn't save image");
+    let format = ImageFormat::from_path(path).unwrap_or_else(|| exit(format!("can't guess image format of {}", path)));
+    let tiles: Vec<Framebuffer> = matches.values_of("TILE_PATHS")
+        .unwrap()
+        .map(|tile_path| input::load_image(tile_path).unwrap_or_else(|e| exit(format!("can't load {}: {}", tile_path, e))))
+        .collect();
+    let width = tiles[0].get_width();
+    if tiles.iter().any(|tile| tile.get_width() != width) {
+        exit("tiles are of different widths".to_string());
+    }
+    let mut merged = Framebuffer::new(width, tiles.iter().map(|tile| tile.get_height()).sum());
+    // aov layers of exr tiles
+    for layer in tiles[0].get_layers() {
+        merged.add_layer(&layer.name);
+    }
+    let mut top = 0;
+    for tile in &tiles {
+        merged.paste(tile, 0, top);
+        top += tile.get_height();
+    }
+    // the tiles are tone mapped already or keep their radiance
+    check_saved(output::save_framebuffer(path, format, &merged, ToneMapping::Clamp, 0.0));
+}
+
+// "1..48" renders frames 1 to 48, both included
+fn parse_frames(frames: &str) -> Option<(u32, u32)> {
+    let mut bounds = frames.splitn(2, "..");
+    let first = bounds.next()?.trim().parse().ok()?;
+    let last = bounds.next()?.trim().parse().ok()?;
+    if first > last {
+        return None;
+    }
+    Some((first, last))
+}
+
+// a run of # in the path is replaced by the zero padded frame number, render.png becomes render.0001.png
+fn get_frame_path(path: &str, frame: u32, last: u32) -> String {
+    if let Some(end) = path.rfind('#') {
+        let start = path[..end].rfind(|c| c != '#').map_or(0, |i| i + 1);
+        return format!("{}{:0width$}{}", &path[..start], frame, &path[end + 1..], width = end + 1 - start);
+    }
+    let width = last.to_string().len().max(4);
+    let path = Path::new(path);
+    let file_name = match path.extension() {
+        Some(extension) => format!(
+            "{}.{:0width$}.{}",
+            path.file_stem().unwrap().to_string_lossy(),
+            frame,
+            extension.to_string_lossy(),
+            width = width,
+        ),
+        None => format!("{}.{:0width$}", path.file_name().unwrap().to_string_lossy(), frame, width = width),
+    };
+    path.with_file_name(file_name).to_str().unwrap().to_string()
 }
 
-fn make_scene(size: u32) -> Scene {
+// next to the main image, render.png gets render.depth.png
+fn save_aov(path: &str, format: ImageFormat, framebuffer: &Framebuffer, aov: Aov) {
+    if path == output::STDOUT_PATH {
+        clap::Error::with_description(
+            "aovs can't be written to stdout, use .exr or a file path",
+            clap::ErrorKind::InvalidValue,
+        ).exit()
+    }
+    let layer = framebuffer.get_layer(aov.get_name()).unwrap();
+    let pixels = if format.is_high_dynamic_range() {
+        layer.get_pixels().to_vec()
+    } else {
+        aov.get_display_pixels(layer)
+    };
+    let path = Path::new(path);
+    let file_name = match path.extension() {
+        Some(extension) => format!(
+            "{}.{}.{}",
+            path.file_stem().unwrap().to_string_lossy(),
+            aov.get_name(),
+            extension.to_string_lossy(),
+        ),
+        None => format!("{}.{}", path.file_name().unwrap().to_string_lossy(), aov.get_name()),
+    };
+    let aov_path = path.with_file_name(file_name);
+    let aov_framebuffer = Framebuffer::from_pixels(framebuffer.get_width(), framebuffer.get_height(), pixels);
+    check_saved(output::save_framebuffer(aov_path.to_str().unwrap(), format, &aov_framebuffer, ToneMapping::Clamp, 0.0));
+}
+
+// wider images see more to the sides, the scene stays in their middle
+fn make_scene((width, height): (u32, u32)) -> Scene {
+    let side = (width - height) / 2;
     let sphere1 = Sphere {
-        center: Point::new(
+        center: Point3::new(
             -500.0,
-            (size / 3) as f64,
+            (side + height / 3) as f64,
             80.0,
         ),
         radius: 80.0,
         color: Color::new(0, 180, 0),
         is_mirror: false,
+        texture: None,
+        bump: None,
     };
     let sphere2 = Sphere {
-        center: Point::new(
+        center: Point3::new(
             -500.0,
-            (2 * size / 3) as f64,
+            (side + 2 * height / 3) as f64,
             80.0,
         ),
         radius: 80.0,
         color: Color::new(180, 0, 0),
         is_mirror: true,
+        texture: None,
+        bump: None,
     };
     Scene {
-        floor: Floor::new(64.0, raytracer::BLACK, raytracer::WHITE),
-        light_source: Point::new(
+        floor: Some(Floor::new(64.0, raytracer::BLACK, raytracer::WHITE)),
+        light_source: Point3::new(
             -1000.0,
-            (size / 2) as f64,
-            (size / 2) as f64,
+            (width / 2) as f64,
+            (height / 2) as f64,
         ),
-        sky_color: Color::new(0, 0, 180),
+        background: Rc::new(Uniform { color: HdrColor::from_color(Color::new(0, 0, 180)) }),
         spheres: vec![sphere1, sphere2],
-        eye: Point::new(
-            (size / 2) as f64,
-            (size / 2) as f64,
-            (size / 2) as f64
+        objects: vec![],
+        eye: Point3::new(
+            (height / 2) as f64,
+            (width / 2) as f64,
+            (height / 2) as f64
         ),
+        // the spheres are 900 away from the default eye
+        camera: Camera { focus_distance: 900.0, ..Camera::pinhole() },
+        tone_mapping: ToneMapping::Clamp,
+        exposure: 0.0,
+    }
+}
+
+
+// two seconds that start from the still scene, the green sphere hops while the light swings
+// to the right and back and the eye rises a little
+fn make_animation((width, height): (u32, u32)) -> SceneAnimation {
+    let (middle, half) = ((width / 2) as f64, (height / 2) as f64);
+    let green_center = Point3::new(-500.0, ((width - height) / 2 + height / 3) as f64, 80.0);
+    SceneAnimation {
+        eye: Track::new(vec![
+            Keyframe::smooth(0.0, Point3::new(half, middle, half)),
+            Keyframe::smooth(2.0, Point3::new(half, middle, half + 100.0)),
+        ]),
+        light_source: Track::new(vec![
+            Keyframe::smooth(0.0, Point3::new(-1000.0, middle, half)),
+            Keyframe::smooth(1.0, Point3::new(-1000.0, middle + half, half)),
+            Keyframe::smooth(2.0, Point3::new(-1000.0, middle, half)),
+        ]),
+        spheres: vec![SphereAnimation {
+            index: 0,
+            center: Track::new(vec![
+                Keyframe::smooth(0.0, green_center),
+                Keyframe::smooth(0.5, green_center + Vec3::new(0.0, 0.0, 160.0)),
+                Keyframe::smooth(1.0, green_center),
+            ]),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+fn get_f64_arg(matches: &ArgMatches, name: &str, default: f64) -> f64 {
+    if matches.is_present(name) {
+        value_t!(matches, name, f64).unwrap_or_else(|e| e.exit())
+    } else {
+        default
+    }
+}
+
+fn get_optional_arg<T: FromStr>(matches: &ArgMatches, name: &str) -> Option<T> {
+    if matches.is_present(name) {
+        Some(value_t!(matches, name, T).unwrap_or_else(|e| e.exit()))
+    } else {
+        None
     }
 }
 
+// in seconds, which can't be negative or endless
+fn get_duration_arg(matches: &ArgMatches, name: &str) -> Option<Duration> {
+    get_optional_arg(matches, name).map(|seconds: f64| {
+        Duration::try_from_secs_f64(seconds).unwrap_or_else(|_| {
+            clap::Error::with_description(
+                &format!("--{} should be a number of seconds, not {}", name, seconds),
+                clap::ErrorKind::InvalidValue,
+            ).exit()
+        })
+    })
+}
 
-fn color_to_pixel(color: Color) -> Pixel {
-    return Pixel::new(color.r, color.g, color.b);
+fn check_saved(result: io::Result<()>) {
+    match result {
+        // the other end of a pipeline doesn't want the rest of the image
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => {}
+        other => other.expect("couldn't save image"),
+    }
+}
+
+fn get_image_format(matches: &ArgMatches, path: &str) -> ImageFormat {
+    let format = match matches.value_of("format") {
+        Some(name) => ImageFormat::from_name(name),
+        None if path == output::STDOUT_PATH => Some(ImageFormat::Ppm),
+        None => ImageFormat::from_path(path),
+    };
+    let format = match (format, matches.value_of("exr-precision")) {
+        (Some(ImageFormat::Exr(_)), Some("float")) => Some(ImageFormat::Exr(ExrPrecision::Float)),
+        _ => format,
+    };
+    format.unwrap_or_else(|| {
+        clap::Error::with_description(
+            &format!("can't guess image format of {}, use --format", path),
+            clap::ErrorKind::InvalidValue,
+        ).exit()
+    })
 }
@@ -0,0 +1,361 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, AddAssign, Deref, Div, Mul, Neg, Sub, SubAssign};
+
+
+// direction or offset
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vec3 {
+    pub fn new(x: f64, y: f64, z: f64) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    pub fn zero() -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    pub fn dot(self, other: Vec3) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(self) -> f64 {
+        self.length_squared().sqrt()
+    }
+
+    pub fn length_squared(self) -> f64 {
+        self.dot(self)
+    }
+
+    // zero vector stays zero
+    pub fn normalize(self) -> Vec3 {
+        let length = self.length();
+        if length == 0.0 {
+            return self;
+        }
+        self / length
+    }
+
+    // mirror reflection, keeps the length
+    pub fn reflect(self, normal: Normal3) -> Vec3 {
+        let normal = *normal;
+        self - normal * (2.0 * self.dot(normal))
+    }
+
+    // unit direction bent by snell's law, eta is the ratio of the refractive indices
+    // from the side of the normal to the other one, None on total internal reflection
+    pub fn refract(self, normal: Normal3, eta: f64) -> Option<Vec3> {
+        let direction = self.normalize();
+        let cos_in = -direction.dot(*normal);
+        let sin_out_squared = eta.powi(2) * (1.0 - cos_in.powi(2));
+        if sin_out_squared > 1.0 {
+            return None;
+        }
+        let cos_out = (1.0 - sin_out_squared).sqrt();
+        Some(direction * eta + *normal * (eta * cos_in - cos_out))
+    }
+
+    pub fn to_point(self) -> Point3 {
+        Point3::new(self.x, self.y, self.z)
+    }
+}
+
+impl fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Vec3(x={}, y={}, z={})", self.x, self.y, self.z)
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, other: Vec3) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, other: Vec3) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl Mul<f64> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, scale: f64) -> Vec3 {
+        Vec3::new(self.x * scale, self.y * scale, self.z * scale)
+    }
+}
+
+// component-wise
+impl Mul for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x * other.x, self.y * other.y, self.z * other.z)
+    }
+}
+
+impl Div<f64> for Vec3 {
+    type Output = Vec3;
+
+    fn div(self, scale: f64) -> Vec3 {
+        Vec3::new(self.x / scale, self.y / scale, self.z / scale)
+    }
+}
+
+// component-wise
+impl Div for Vec3 {
+    type Output = Vec3;
+
+    fn div(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x / other.x, self.y / other.y, self.z / other.z)
+    }
+}
+
+// position, points can be moved by vectors and subtracted from each other
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Point3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Point3 {
+    pub fn new(x: f64, y: f64, z: f64) -> Point3 {
+        Point3 { x, y, z }
+    }
+
+    pub fn origin() -> Point3 {
+        Point3::new(0.0, 0.0, 0.0)
+    }
+
+    // offset from the origin
+    pub fn to_vec(self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+}
+
+impl fmt::Display for Point3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Point3(x={}, y={}, z={})", self.x, self.y, self.z)
+    }
+}
+
+impl Sub for Point3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Point3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Add<Vec3> for Point3 {
+    type Output = Point3;
+
+    fn add(self, offset: Vec3) -> Point3 {
+        Point3::new(self.x + offset.x, self.y + offset.y, self.z + offset.z)
+    }
+}
+
+impl Sub<Vec3> for Point3 {
+    type Output = Point3;
+
+    fn sub(self, offset: Vec3) -> Point3 {
+        Point3::new(self.x - offset.x, self.y - offset.y, self.z - offset.z)
+    }
+}
+
+// unit length direction perpendicular to a surface, reads like a Vec3
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Normal3(Vec3);
+
+impl Normal3 {
+    // normalizes the vector, which must not be zero
+    pub fn new(vector: Vec3) -> Normal3 {
+        Normal3::try_new(vector).expect("normal can't be zero")
+    }
+
+    // None for zero vectors and ones with infinite or nan components, like those of degenerate shapes
+    pub fn try_new(vector: Vec3) -> Option<Normal3> {
+        let length = vector.length();
+        if length > 0.0 && length.is_finite() {
+            Some(Normal3(vector / length))
+        } else {
+            None
+        }
+    }
+
+    pub fn to_vec(self) -> Vec3 {
+        self.0
+    }
+}
+
+impl Deref for Normal3 {
+    type Target = Vec3;
+
+    fn deref(&self) -> &Vec3 {
+        &self.0
+    }
+}
+
+impl fmt::Display for Normal3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Normal3(x={}, y={}, z={})", self.0.x, self.0.y, self.0.z)
+    }
+}
+
+impl Neg for Normal3 {
+    type Output = Normal3;
+
+    fn neg(self) -> Normal3 {
+        Normal3(-self.0)
+    }
+}
+
+impl Mul<f64> for Normal3 {
+    type Output = Vec3;
+
+    fn mul(self, scale: f64) -> Vec3 {
+        self.0 * scale
+    }
+}
+
+// row major, multiplies column vectors
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Mat4 {
+    pub rows: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(rows: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 { rows }
+    }
+
+    pub fn identity() -> Mat4 {
+        Mat4::new([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.rows[j][i];
+            }
+        }
+        Mat4::new(rows)
+    }
+
+    // gauss-jordan elimination with partial pivoting, None for singular matrices
+    pub fn inverse(&self) -> Option<Mat4> {
+        if self.rows.iter().flatten().any(|value| !value.is_finite()) {
+            return None;
+        }
+        let mut left = self.rows;
+        let mut right = Mat4::identity().rows;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|a, b| left[*a][column].abs().partial_cmp(&left[*b][column].abs()).unwrap_or(Ordering::Equal))
+                .unwrap();
+            if left[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            left.swap(column, pivot);
+            right.swap(column, pivot);
+            let scale = 1.0 / left[column][column];
+            for j in 0..4 {
+                left[column][j] *= scale;
+                right[column][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = left[row][column];
+                for j in 0..4 {
+                    left[row][j] -= factor * left[column][j];
+                    right[row][j] -= factor * right[column][j];
+                }
+            }
+        }
+        // nearly singular matrices can overflow on the way
+        if right.iter().flatten().any(|value| !value.is_finite()) {
+            return None;
+        }
+        Some(Mat4::new(right))
+    }
+
+    pub fn transform_point(&self, point: Point3) -> Point3 {
+        let m = &self.rows;
+        Point3::new(
+            m[0][0] * point.x + m[0][1] * point.y + m[0][2] * point.z + m[0][3],
+            m[1][0] * point.x + m[1][1] * point.y + m[1][2] * point.z + m[1][3],
+            m[2][0] * point.x + m[2][1] * point.y + m[2][2] * point.z + m[2][3],
+        )
+    }
+
+    // ignores translation
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        let m = &self.rows;
+        Vec3::new(
+            m[0][0] * vector.x + m[0][1] * vector.y + m[0][2] * vector.z,
+            m[1][0] * vector.x + m[1][1] * vector.y + m[1][2] * vector.z,
+            m[2][0] * vector.x + m[2][1] * vector.y + m[2][2] * vector.z,
+        )
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.rows[i][k] * other.rows[k][j]).sum();
+            }
+        }
+        Mat4::new(rows)
+    }
+}
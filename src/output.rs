@@ -0,0 +1,370 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use png;
+
+use super::{Color, HdrColor, ToneMapping};
+use framebuffer::Framebuffer;
+
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ImageFormat {
+    Bmp,
+    Png,
+    // binary P6 netpbm
+    Ppm,
+    // Radiance RGBE
+    Hdr,
+    Exr(ExrPrecision),
+    // portable float map
+    Pfm,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+pub const IMAGE_FORMAT_NAMES: [&str; 6] = ["bmp", "png", "ppm", "hdr", "exr", "pfm"];
+
+// images saved to this path go to stdout
+pub const STDOUT_PATH: &str = "-";
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name.to_lowercase().as_str() {
+            "bmp" => Some(ImageFormat::Bmp),
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "hdr" => Some(ImageFormat::Hdr),
+            "exr" => Some(ImageFormat::Exr(ExrPrecision::Half)),
+            "pfm" => Some(ImageFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    // high dynamic range formats store radiance as is, without tone mapping
+    pub fn is_high_dynamic_range(&self) -> bool {
+        match *self {
+            ImageFormat::Hdr | ImageFormat::Exr(_) | ImageFormat::Pfm => true,
+            ImageFormat::Bmp | ImageFormat::Png | ImageFormat::Ppm => false,
+        }
+    }
+
+    pub fn from_path(path: &str) -> Option<ImageFormat> {
+        Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(ImageFormat::from_name)
+    }
+}
+
+// high dynamic range formats get the radiance and the layers, others get tone mapped pixels
+pub fn save_framebuffer(path: &str, format: ImageFormat, framebuffer: &Framebuffer, tone_mapping: ToneMapping, exposure: f64) -> io::Result<()> {
+    let width = framebuffer.get_width();
+    let height = framebuffer.get_height();
+    if !format.is_high_dynamic_range() {
+        return save_image(path, format, width, height, &framebuffer.to_colors(tone_mapping, exposure));
+    }
+    if let ImageFormat::Exr(precision) = format {
+        let mut channels = get_exr_channels("", framebuffer.get_pixels());
+        for layer in framebuffer.get_layers() {
+            channels.extend(get_exr_channels(&format!("{}.", layer.name), layer.get_pixels()));
+        }
+        let mut writer = create_writer(path)?;
+        write_exr(&mut writer, width, height, channels, precision)?;
+        return writer.flush();
+    }
+    save_hdr_image(path, format, width, height, framebuffer.get_pixels())
+}
+
+// pixels go row by row, starting from the top left corner
+pub fn save_image(path: &str, format: ImageFormat, width: u32, height: u32, pixels: &[Color]) -> io::Result<()> {
+    assert_eq!(pixels.len(), (width * height) as usize);
+    let mut writer = create_writer(path)?;
+    match format {
+        ImageFormat::Bmp => write_bmp(&mut writer, width, height, pixels)?,
+        ImageFormat::Png => write_png(&mut writer, width, height, pixels)?,
+        ImageFormat::Ppm => write_ppm(&mut writer, width, height, pixels)?,
+        _ => return Err(get_unsupported_format_error(format)),
+    }
+    writer.flush()
+}
+
+// pixels go in the same order as in save_image
+pub fn save_hdr_image(path: &str, format: ImageFormat, width: u32, height: u32, pixels: &[HdrColor]) -> io::Result<()> {
+    assert_eq!(pixels.len(), (width * height) as usize);
+    let mut writer = create_writer(path)?;
+    match format {
+        ImageFormat::Hdr => write_hdr(&mut writer, width, height, pixels)?,
+        ImageFormat::Pfm => write_pfm(&mut writer, width, height, pixels)?,
+        ImageFormat::Exr(precision) => write_exr(&mut writer, width, height, get_exr_channels("", pixels), precision)?,
+        _ => return Err(get_unsupported_format_error(format)),
+    }
+    writer.flush()
+}
+
+fn create_writer(path: &str) -> io::Result<BufWriter<Box<dyn Write>>> {
+    let writer: Box<dyn Write> = if path == STDOUT_PATH {
+        Box::new(io::stdout())
+    } else {
+        Box::new(File::create(path)?)
+    };
+    Ok(BufWriter::new(writer))
+}
+
+fn get_unsupported_format_error(format: ImageFormat) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("can't save this kind of image as {:?}", format))
+}
+
+// 24 bit uncompressed bitmap
+fn write_bmp<W: Write>(writer: &mut W, width: u32, height: u32, pixels: &[Color]) -> io::Result<()> {
+    let header_size = 14 + 40;
+    let row_size = (3 * width).div_ceil(4) * 4;
+    let data_size = row_size * height;
+    writer.write_all(b"BM")?;
+    writer.write_all(&(header_size + data_size).to_le_bytes())?;
+    // reserved
+    writer.write_all(&[0; 4])?;
+    writer.write_all(&header_size.to_le_bytes())?;
+
+    writer.write_all(&40_u32.to_le_bytes())?;
+    writer.write_all(&(width as i32).to_le_bytes())?;
+    writer.write_all(&(height as i32).to_le_bytes())?;
+    // planes and bits per pixel
+    writer.write_all(&1_u16.to_le_bytes())?;
+    writer.write_all(&24_u16.to_le_bytes())?;
+    // no compression
+    writer.write_all(&0_u32.to_le_bytes())?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    // horizontal and vertical resolution
+    writer.write_all(&1000_i32.to_le_bytes())?;
+    writer.write_all(&1000_i32.to_le_bytes())?;
+    // palette
+    writer.write_all(&[0; 8])?;
+
+    let padding = vec![0; (row_size - 3 * width) as usize];
+    // scanlines go from the bottom to the top
+    for scanline in pixels.chunks(width as usize).rev() {
+        for color in scanline {
+            writer.write_all(&[color.b, color.g, color.r])?;
+        }
+        writer.write_all(&padding)?;
+    }
+    Ok(())
+}
+
+fn write_png<W: Write>(writer: &mut W, width: u32, height: u32, pixels: &[Color]) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut data = Vec::with_capacity(pixels.len() * 3);
+    for color in pixels {
+        data.extend_from_slice(&[color.r, color.g, color.b]);
+    }
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+fn write_ppm<W: Write>(writer: &mut W, width: u32, height: u32, pixels: &[Color]) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+    for color in pixels {
+        writer.write_all(&[color.r, color.g, color.b])?;
+    }
+    Ok(())
+}
+
+fn write_pfm<W: Write>(writer: &mut W, width: u32, height: u32, pixels: &[HdrColor]) -> io::Result<()> {
+    // negative scale means little endian floats
+    write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+    // scanlines go from the bottom to the top
+    for scanline in pixels.chunks(width as usize).rev() {
+        for pixel in scanline {
+            for value in &[pixel.r, pixel.g, pixel.b] {
+                writer.write_all(&(*value as f32).to_bits().to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_hdr<W: Write>(writer: &mut W, width: u32, height: u32, pixels: &[HdrColor]) -> io::Result<()> {
+    write!(writer, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+    let is_rle_width = (8..0x8000).contains(&width);
+    for scanline in pixels.chunks(width as usize) {
+        let rgbe: Vec<[u8; 4]> = scanline.iter().map(|pixel| to_rgbe(*pixel)).collect();
+        if !is_rle_width {
+            // readers expect flat scanlines for these widths
+            for value in &rgbe {
+                writer.write_all(value)?;
+            }
+            continue;
+        }
+        writer.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+        // components go one after another, as literal runs of at most 128 bytes
+        for component in 0..4 {
+            let values: Vec<u8> = rgbe.iter().map(|value| value[component]).collect();
+            for run in values.chunks(128) {
+                writer.write_all(&[run.len() as u8])?;
+                writer.write_all(run)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// shared exponent encoding of Radiance .hdr files
+pub fn to_rgbe(color: HdrColor) -> [u8; 4] {
+    let r = color.r.max(0.0);
+    let g = color.g.max(0.0);
+    let b = color.b.max(0.0);
+    let max_component = r.max(g).max(b);
+    if max_component < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    let mut exponent = max_component.log2().floor() as i32 + 1;
+    // log2 can be off by one near powers of two
+    if max_component / 2.0_f64.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let scale = 256.0 / 2.0_f64.powi(exponent);
+    [
+        (r * scale).min(255.0) as u8,
+        (g * scale).min(255.0) as u8,
+        (b * scale).min(255.0) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+pub fn from_rgbe(rgbe: [u8; 4]) -> HdrColor {
+    if rgbe[3] == 0 {
+        return HdrColor::new(0.0, 0.0, 0.0);
+    }
+    let scale = 2.0_f64.powi(rgbe[3] as i32 - 136);
+    HdrColor::new(rgbe[0] as f64 * scale, rgbe[1] as f64 * scale, rgbe[2] as f64 * scale)
+}
+
+fn get_exr_channels(prefix: &str, pixels: &[HdrColor]) -> Vec<(String, Vec<f64>)> {
+    vec![
+        (format!("{}R", prefix), pixels.iter().map(|p| p.r).collect()),
+        (format!("{}G", prefix), pixels.iter().map(|p| p.g).collect()),
+        (format!("{}B", prefix), pixels.iter().map(|p| p.b).collect()),
+    ]
+}
+
+// uncompressed single part scanline OpenEXR file
+fn write_exr<W: Write>(writer: &mut W, width: u32, height: u32, mut channels: Vec<(String, Vec<f64>)>, precision: ExrPrecision) -> io::Result<()> {
+    channels.sort_by(|a, b| a.0.cmp(&b.0));
+    let (pixel_type, pixel_size) = match precision {
+        ExrPrecision::Half => (1, 2),
+        ExrPrecision::Float => (2, 4),
+    };
+
+    let mut header = vec![];
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+    let mut channel_list = vec![];
+    for (name, _) in &channels {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&to_le_i32(pixel_type));
+        // pLinear and reserved bytes
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        // x and y sampling
+        channel_list.extend_from_slice(&to_le_i32(1));
+        channel_list.extend_from_slice(&to_le_i32(1));
+    }
+    channel_list.push(0);
+    push_exr_attribute(&mut header, "channels", "chlist", &channel_list);
+    push_exr_attribute(&mut header, "compression", "compression", &[0]);
+    let mut window = vec![];
+    for value in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&to_le_i32(*value));
+    }
+    push_exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    push_exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    push_exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    push_exr_attribute(&mut header, "pixelAspectRatio", "float", &1.0_f32.to_bits().to_le_bytes());
+    push_exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    push_exr_attribute(&mut header, "screenWindowWidth", "float", &1.0_f32.to_bits().to_le_bytes());
+    header.push(0);
+    writer.write_all(&header)?;
+
+    let scanline_size = width as usize * pixel_size * channels.len();
+    let first_offset = header.len() + height as usize * 8;
+    for y in 0..height as usize {
+        // each block is y coordinate, data size and data
+        let offset = first_offset + y * (8 + scanline_size);
+        writer.write_all(&(offset as u64).to_le_bytes())?;
+    }
+    for y in 0..height as usize {
+        writer.write_all(&to_le_i32(y as i32))?;
+        writer.write_all(&to_le_i32(scanline_size as i32))?;
+        for (_, values) in &channels {
+            for value in &values[y * width as usize..(y + 1) * width as usize] {
+                match precision {
+                    ExrPrecision::Half => writer.write_all(&to_half(*value as f32).to_le_bytes())?,
+                    ExrPrecision::Float => writer.write_all(&(*value as f32).to_bits().to_le_bytes())?,
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn push_exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&to_le_i32(value.len() as i32));
+    header.extend_from_slice(value);
+}
+
+fn to_le_i32(value: i32) -> [u8; 4] {
+    value.to_le_bytes()
+}
+
+// IEEE 754 half precision float, rounded to nearest even
+pub fn to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+    if exponent == 0xff {
+        // infinity or nan
+        let nan_bit = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | nan_bit;
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        // subnormal half
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let rounded = if remainder > halfway || (remainder == halfway && half_mantissa & 1 == 1) {
+            half_mantissa + 1
+        } else {
+            half_mantissa
+        };
+        return sign | rounded as u16;
+    }
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    // carrying into the exponent is fine, it rounds up to the next power of two or infinity
+    let rounded = if remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1) {
+        half + 1
+    } else {
+        half
+    };
+    sign | rounded as u16
+}
@@ -0,0 +1,244 @@
+use std::mem;
+
+use super::{get_quadratic_equation_roots, get_quartic_equation_roots, Color, ColoredPoint, Ray};
+use math::{Normal3, Point3, Vec3};
+use shape::Shape;
+
+
+// axis aligned, oriented boxes are instances of it with a rotation
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Cuboid {
+    pub min: Point3,
+    pub max: Point3,
+    pub color: Color,
+}
+
+impl Cuboid {
+    pub fn new(center: Point3, size: Vec3, color: Color) -> Cuboid {
+        Cuboid { min: center - size / 2.0, max: center + size / 2.0, color }
+    }
+
+    // slabs between the opposite faces, the ray is inside all of them at once or misses
+    fn get_hits(&self, ray: Ray) -> Vec<(f64, Vec3)> {
+        if ray.direction.length() == 0.0 {
+            return vec![];
+        }
+        let start = get_components(ray.start.to_vec());
+        let direction = get_components(ray.direction);
+        let min = get_components(self.min.to_vec());
+        let max = get_components(self.max.to_vec());
+        let mut near = (f64::NEG_INFINITY, Vec3::zero());
+        let mut far = (f64::INFINITY, Vec3::zero());
+        for axis in 0..3 {
+            if direction[axis] == 0.0 {
+                if start[axis] < min[axis] || start[axis] > max[axis] {
+                    return vec![];
+                }
+                continue;
+            }
+            let normal = get_axis(axis);
+            let mut entry = ((min[axis] - start[axis]) / direction[axis], -normal);
+            let mut exit = ((max[axis] - start[axis]) / direction[axis], normal);
+            if entry.0 > exit.0 {
+                mem::swap(&mut entry, &mut exit);
+            }
+            if entry.0 > near.0 {
+                near = entry;
+            }
+            if exit.0 < far.0 {
+                far = exit;
+            }
+        }
+        if near.0 > far.0 {
+            return vec![];
+        }
+        vec![near, far]
+    }
+}
+
+impl Shape for Cuboid {
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        to_colored_points(ray, self.get_hits(ray), self.color)
+    }
+}
+
+// capped, stands on its base along the z axis
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Cylinder {
+    // center of the bottom cap
+    pub base: Point3,
+    pub radius: f64,
+    pub height: f64,
+    pub color: Color,
+}
+
+impl Cylinder {
+    fn get_hits(&self, ray: Ray) -> Vec<(f64, Vec3)> {
+        let p = ray.start - self.base;
+        let d = ray.direction;
+        let roots = get_quadratic_equation_roots(
+            d.x * d.x + d.y * d.y,
+            2.0 * (p.x * d.x + p.y * d.y),
+            p.x * p.x + p.y * p.y - self.radius.powi(2),
+        );
+        let mut hits = vec![];
+        for t in roots {
+            let local = p + d * t;
+            if local.z >= 0.0 && local.z <= self.height {
+                hits.push((t, Vec3::new(local.x, local.y, 0.0)));
+            }
+        }
+        let top = self.base + Vec3::new(0.0, 0.0, self.height);
+        hits.extend(get_disk_hit(ray, self.base, -get_axis(2), self.radius));
+        hits.extend(get_disk_hit(ray, top, get_axis(2), self.radius));
+        hits
+    }
+}
+
+impl Shape for Cylinder {
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        to_colored_points(ray, self.get_hits(ray), self.color)
+    }
+}
+
+// capped at its base, the apex is above the base along the z axis
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Cone {
+    // center of the cap
+    pub base: Point3,
+    pub radius: f64,
+    pub height: f64,
+    pub color: Color,
+}
+
+impl Cone {
+    fn get_hits(&self, ray: Ray) -> Vec<(f64, Vec3)> {
+        let p = ray.start - self.base;
+        let d = ray.direction;
+        // x^2 + y^2 = (slope * (height - z))^2
+        let slope_squared = (self.radius / self.height).powi(2);
+        let below_apex = self.height - p.z;
+        let roots = get_quadratic_equation_roots(
+            d.x * d.x + d.y * d.y - slope_squared * d.z * d.z,
+            2.0 * (p.x * d.x + p.y * d.y + slope_squared * below_apex * d.z),
+            p.x * p.x + p.y * p.y - slope_squared * below_apex * below_apex,
+        );
+        let mut hits = vec![];
+        for t in roots {
+            let local = p + d * t;
+            if local.z >= 0.0 && local.z <= self.height {
+                let normal = Vec3::new(local.x, local.y, slope_squared * (self.height - local.z));
+                // the apex has no normal, it points up as well as any other way
+                let normal = if normal.length() < 1e-9 { get_axis(2) } else { normal };
+                hits.push((t, normal));
+            }
+        }
+        hits.extend(get_disk_hit(ray, self.base, -get_axis(2), self.radius));
+        hits
+    }
+}
+
+impl Shape for Cone {
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        to_colored_points(ray, self.get_hits(ray), self.color)
+    }
+}
+
+// flat and round, seen from both sides but its normal stays the same
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Disk {
+    pub center: Point3,
+    pub normal: Normal3,
+    pub radius: f64,
+    pub color: Color,
+}
+
+impl Shape for Disk {
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        let hits = get_disk_hit(ray, self.center, *self.normal, self.radius);
+        to_colored_points(ray, hits.into_iter().collect(), self.color)
+    }
+}
+
+// ring around the z axis
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Torus {
+    pub center: Point3,
+    // from the center to the middle of the tube
+    pub major_radius: f64,
+    // of the tube
+    pub minor_radius: f64,
+    pub color: Color,
+}
+
+impl Torus {
+    fn get_hits(&self, ray: Ray) -> Vec<(f64, Vec3)> {
+        let length = ray.direction.length();
+        if length == 0.0 {
+            return vec![];
+        }
+        // the quartic is better conditioned for a unit direction
+        let d = ray.direction / length;
+        let p = ray.start - self.center;
+        let major_squared = self.major_radius.powi(2);
+        let minor_squared = self.minor_radius.powi(2);
+        // (|p|^2 + R^2 - r^2)^2 = 4 * R^2 * (x^2 + y^2)
+        let e = p.length_squared() - major_squared - minor_squared;
+        let f = p.dot(d);
+        let roots = get_quartic_equation_roots(
+            1.0,
+            4.0 * f,
+            2.0 * e + 4.0 * f * f + 4.0 * major_squared * d.z * d.z,
+            4.0 * f * e + 8.0 * major_squared * p.z * d.z,
+            e * e - 4.0 * major_squared * (minor_squared - p.z * p.z),
+        );
+        let mut hits = vec![];
+        for t in roots {
+            let local = p + d * t;
+            let sum = local.length_squared() - major_squared - minor_squared;
+            let normal = Vec3::new(local.x * sum, local.y * sum, local.z * (sum + 2.0 * major_squared));
+            if normal.length() > 0.0 {
+                hits.push((t / length, normal));
+            }
+        }
+        hits
+    }
+}
+
+impl Shape for Torus {
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        to_colored_points(ray, self.get_hits(ray), self.color)
+    }
+}
+
+// distance along the ray and the outward normal, which doesn't have to be normalized
+fn get_disk_hit(ray: Ray, center: Point3, normal: Vec3, radius: f64) -> Option<(f64, Vec3)> {
+    let denominator = ray.direction.dot(normal);
+    if denominator == 0.0 {
+        return None;
+    }
+    let t = (center - ray.start).dot(normal) / denominator;
+    let point = ray.start + ray.direction * t;
+    if (point - center).length_squared() > radius * radius {
+        return None;
+    }
+    Some((t, normal))
+}
+
+fn to_colored_points(ray: Ray, hits: Vec<(f64, Vec3)>, color: Color) -> Vec<ColoredPoint> {
+    hits.into_iter()
+        .filter(|&(t, _)| t >= 0.0)
+        // degenerate shapes, like a flat cone, have hits without a normal
+        .filter_map(|(t, normal)| Some(ColoredPoint::new(ray.start + ray.direction * t, color, Normal3::try_new(normal)?)))
+        .collect()
+}
+
+fn get_components(vector: Vec3) -> [f64; 3] {
+    [vector.x, vector.y, vector.z]
+}
+
+fn get_axis(axis: usize) -> Vec3 {
+    let mut components = [0.0; 3];
+    components[axis] = 1.0;
+    Vec3::new(components[0], components[1], components[2])
+}
@@ -0,0 +1,194 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use super::HdrColor;
+use aov::Aov;
+use framebuffer::{Framebuffer, Region};
+
+// starts checkpoint files, the digit goes up when their layout changes
+const CHECKPOINT_SIGNATURE: &[u8; 8] = b"RTACCUM1";
+
+
+// samples added up over many passes, the image is their average and keeps getting better
+pub struct Accumulation {
+    width: u32,
+    height: u32,
+    sums: Vec<HdrColor>,
+    counts: Vec<u32>,
+    // filled by the first sample of every pixel
+    aovs: Framebuffer,
+}
+
+impl Accumulation {
+    pub fn new(width: u32, height: u32, aovs: &[Aov]) -> Accumulation {
+        let mut aov_framebuffer = Framebuffer::new(width, height);
+        for aov in aovs {
+            aov_framebuffer.add_layer(aov.get_name());
+        }
+        let pixel_count = (width * height) as usize;
+        Accumulation {
+            width,
+            height,
+            sums: vec![HdrColor::new(0.0, 0.0, 0.0); pixel_count],
+            counts: vec![0; pixel_count],
+            aovs: aov_framebuffer,
+        }
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_sample_count(&self, column: u32, row: u32) -> u32 {
+        self.counts[self.get_index(column, row)]
+    }
+
+    // passes over the whole image, an interrupted pass doesn't count
+    pub fn get_passes(&self) -> u32 {
+        self.get_passes_in(Region::full(self.width, self.height))
+    }
+
+    pub fn get_passes_in(&self, region: Region) -> u32 {
+        (region.top..region.bottom)
+            .flat_map(|row| (region.left..region.right).map(move |column| (column, row)))
+            .map(|(column, row)| self.get_sample_count(column, row))
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn add_sample(&mut self, column: u32, row: u32, radiance: HdrColor) {
+        let index = self.get_index(column, row);
+        self.sums[index] = self.sums[index] + radiance;
+        self.counts[index] += 1;
+    }
+
+    pub fn get_aovs_mut(&mut self) -> &mut Framebuffer {
+        &mut self.aovs
+    }
+
+    // current estimate with the aov layers, black where nothing is sampled yet
+    pub fn to_framebuffer(&self) -> Framebuffer {
+        let mut framebuffer = self.aovs.clone();
+        for row in 0..self.height {
+            for column in 0..self.width {
+                let index = self.get_index(column, row);
+                let count = self.counts[index].max(1);
+                framebuffer.set_pixel(column, row, self.sums[index] * (1.0 / count as f64));
+            }
+        }
+        framebuffer
+    }
+
+    // the seed and the sample counts are all the random state, the next sample of a pixel
+    // continues where the last one stopped
+    pub fn save(&self, path: &str, seed: u64) -> io::Result<()> {
+        // a render killed while saving keeps the previous checkpoint
+        let partial_path = format!("{}.partial", path);
+        let mut writer = BufWriter::new(File::create(&partial_path)?);
+        self.write(&mut writer, seed)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&partial_path, path)
+    }
+
+    // also returns the seed the samples were taken with
+    pub fn open(path: &str) -> io::Result<(Accumulation, u64)> {
+        Accumulation::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    // little endian, radiance sums are kept as f64 so resuming doesn't lose precision
+    pub fn write<W: Write>(&self, writer: &mut W, seed: u64) -> io::Result<()> {
+        writer.write_all(CHECKPOINT_SIGNATURE)?;
+        writer.write_all(&self.width.to_le_bytes())?;
+        writer.write_all(&self.height.to_le_bytes())?;
+        writer.write_all(&seed.to_le_bytes())?;
+        for (sum, count) in self.sums.iter().zip(self.counts.iter()) {
+            write_color(writer, *sum)?;
+            writer.write_all(&count.to_le_bytes())?;
+        }
+        let layers = self.aovs.get_layers();
+        writer.write_all(&(layers.len() as u32).to_le_bytes())?;
+        for layer in layers {
+            writer.write_all(&(layer.name.len() as u32).to_le_bytes())?;
+            writer.write_all(layer.name.as_bytes())?;
+            for pixel in layer.get_pixels() {
+                write_color(writer, *pixel)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<(Accumulation, u64)> {
+        let mut signature = [0; 8];
+        reader.read_exact(&mut signature)?;
+        if &signature != CHECKPOINT_SIGNATURE {
+            return Err(get_invalid_data_error("not a render checkpoint"));
+        }
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+        let seed = read_u64(reader)?;
+        if width.checked_mul(height).map_or(true, |count| count > 1 << 28) {
+            return Err(get_invalid_data_error("checkpoint is too large"));
+        }
+        let mut accumulation = Accumulation::new(width, height, &[]);
+        for index in 0..(width * height) as usize {
+            accumulation.sums[index] = read_color(reader)?;
+            accumulation.counts[index] = read_u32(reader)?;
+        }
+        let layer_count = read_u32(reader)?;
+        for _ in 0..layer_count {
+            let name_length = read_u32(reader)?;
+            if name_length > 256 {
+                return Err(get_invalid_data_error("checkpoint layer name is too long"));
+            }
+            let mut name = vec![0; name_length as usize];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| get_invalid_data_error("checkpoint layer name isn't utf-8"))?;
+            let layer = accumulation.aovs.add_layer(&name);
+            for row in 0..height {
+                for column in 0..width {
+                    layer.set_pixel(column, row, read_color(reader)?);
+                }
+            }
+        }
+        Ok((accumulation, seed))
+    }
+
+    fn get_index(&self, column: u32, row: u32) -> usize {
+        (row * self.width + column) as usize
+    }
+}
+
+fn write_color<W: Write>(writer: &mut W, color: HdrColor) -> io::Result<()> {
+    for value in &[color.r, color.g, color.b] {
+        writer.write_all(&value.to_bits().to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_color<R: Read>(reader: &mut R) -> io::Result<HdrColor> {
+    let r = f64::from_bits(read_u64(reader)?);
+    let g = f64::from_bits(read_u64(reader)?);
+    let b = f64::from_bits(read_u64(reader)?);
+    Ok(HdrColor::new(r, g, b))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn get_invalid_data_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,38 @@
+// xorshift, the same seed gives the same numbers on every run and platform
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let state = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        // zero would stay zero forever
+        Rng { state: if state == 0 { 0x9e37_79b9_7f4a_7c15 } else { state } }
+    }
+
+    // independent sequences for every stream of one seed, e.g. for every pixel
+    pub fn with_stream(seed: u64, stream: u64) -> Rng {
+        Rng::new(mix(seed ^ mix(stream)))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    // 0.0..1.0
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// splitmix64 finalizer, neighbouring values end up far apart
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
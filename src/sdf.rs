@@ -0,0 +1,223 @@
+use std::rc::Rc;
+
+use super::{Color, ColoredPoint, Ray};
+use math::{Normal3, Point3, Vec3};
+use shape::Shape;
+
+
+// signed distance to a surface, negative inside
+pub trait DistanceField {
+    // may underestimate the distance but never overestimate it
+    fn distance_at(&self, point: Point3) -> f64;
+}
+
+pub struct Ball {
+    pub center: Point3,
+    pub radius: f64,
+}
+
+impl DistanceField for Ball {
+    fn distance_at(&self, point: Point3) -> f64 {
+        (point - self.center).length() - self.radius
+    }
+}
+
+// axis aligned, rounding makes the edges round without growing the box
+pub struct Block {
+    pub center: Point3,
+    pub size: Vec3,
+    pub rounding: f64,
+}
+
+impl DistanceField for Block {
+    fn distance_at(&self, point: Point3) -> f64 {
+        let offset = point - self.center;
+        let q = Vec3::new(offset.x.abs(), offset.y.abs(), offset.z.abs()) - self.size / 2.0 + Vec3::new(1.0, 1.0, 1.0) * self.rounding;
+        let outside = Vec3::new(q.x.max(0.0), q.y.max(0.0), q.z.max(0.0)).length();
+        let inside = q.x.max(q.y).max(q.z).min(0.0);
+        outside + inside - self.rounding
+    }
+}
+
+// ring around the z axis
+pub struct Ring {
+    pub center: Point3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+impl DistanceField for Ring {
+    fn distance_at(&self, point: Point3) -> f64 {
+        let offset = point - self.center;
+        let ring = (offset.x.powi(2) + offset.y.powi(2)).sqrt() - self.major_radius;
+        (ring.powi(2) + offset.z.powi(2)).sqrt() - self.minor_radius
+    }
+}
+
+// fractal around the origin that fits into a sphere of radius 1.2, power 8 gives the classic bulb
+pub struct Mandelbulb {
+    pub power: f64,
+    pub iterations: u32,
+}
+
+impl DistanceField for Mandelbulb {
+    fn distance_at(&self, point: Point3) -> f64 {
+        let c = point.to_vec();
+        let mut z = c;
+        let mut derivative = 1.0;
+        let mut radius = z.length();
+        for _ in 0..self.iterations {
+            if radius > 2.0 {
+                break;
+            }
+            // z -> z^power + c in spherical coordinates
+            let theta = (z.z / radius).clamp(-1.0, 1.0).acos() * self.power;
+            let phi = z.y.atan2(z.x) * self.power;
+            derivative = self.power * radius.powf(self.power - 1.0) * derivative + 1.0;
+            let scaled = radius.powf(self.power);
+            z = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos()) * scaled + c;
+            radius = z.length();
+        }
+        if radius == 0.0 {
+            return 0.0;
+        }
+        0.5 * radius.ln() * radius / derivative
+    }
+}
+
+pub struct Union {
+    pub first: Rc<dyn DistanceField>,
+    pub second: Rc<dyn DistanceField>,
+}
+
+impl DistanceField for Union {
+    fn distance_at(&self, point: Point3) -> f64 {
+        self.first.distance_at(point).min(self.second.distance_at(point))
+    }
+}
+
+// blends the surfaces where they are closer than smoothness
+pub struct SmoothUnion {
+    pub first: Rc<dyn DistanceField>,
+    pub second: Rc<dyn DistanceField>,
+    pub smoothness: f64,
+}
+
+impl DistanceField for SmoothUnion {
+    fn distance_at(&self, point: Point3) -> f64 {
+        let first = self.first.distance_at(point);
+        let second = self.second.distance_at(point);
+        if self.smoothness <= 0.0 {
+            return first.min(second);
+        }
+        let h = (0.5 + 0.5 * (second - first) / self.smoothness).clamp(0.0, 1.0);
+        second * (1.0 - h) + first * h - self.smoothness * h * (1.0 - h)
+    }
+}
+
+// copies the field around the origin cell forever, zero periods don't repeat along their axis
+pub struct Repetition {
+    pub field: Rc<dyn DistanceField>,
+    pub period: Vec3,
+}
+
+impl DistanceField for Repetition {
+    fn distance_at(&self, point: Point3) -> f64 {
+        let repeat = |value: f64, period: f64| {
+            if period == 0.0 {
+                return value;
+            }
+            (value + period / 2.0).rem_euclid(period) - period / 2.0
+        };
+        let local = Point3::new(repeat(point.x, self.period.x), repeat(point.y, self.period.y), repeat(point.z, self.period.z));
+        self.field.distance_at(local)
+    }
+}
+
+// turns the field around the z axis more the higher it goes, overestimates distances
+// so objects using it need a smaller step scale
+pub struct Twist {
+    pub field: Rc<dyn DistanceField>,
+    // radians per unit of height
+    pub rate: f64,
+}
+
+impl DistanceField for Twist {
+    fn distance_at(&self, point: Point3) -> f64 {
+        let (sin, cos) = (-point.z * self.rate).sin_cos();
+        let local = Point3::new(point.x * cos - point.y * sin, point.x * sin + point.y * cos, point.z);
+        self.field.distance_at(local)
+    }
+}
+
+// surface of a distance field found by sphere tracing
+pub struct SdfObject {
+    pub field: Rc<dyn DistanceField>,
+    pub color: Color,
+    // distance at which the ray counts as touching the surface
+    pub precision: f64,
+    // rays give up after travelling this far or after max_steps
+    pub max_distance: f64,
+    pub max_steps: u32,
+    // fraction of the distance the ray advances in one step, below 1.0 for fields that overestimate
+    pub step_scale: f64,
+}
+
+impl SdfObject {
+    pub fn new(field: Rc<dyn DistanceField>, color: Color) -> SdfObject {
+        SdfObject { field, color, precision: 1e-4, max_distance: 1e5, max_steps: 1000, step_scale: 1.0 }
+    }
+
+    // distance along the unit direction to the first hit
+    fn march(&self, start: Point3, direction: Vec3) -> Option<f64> {
+        let mut t = 0.0;
+        // rays towards the light start on the surface and have to leave it first
+        let mut is_leaving = self.field.distance_at(start).abs() < self.precision * 10.0;
+        for _ in 0..self.max_steps {
+            let distance = self.field.distance_at(start + direction * t).abs();
+            if is_leaving {
+                is_leaving = distance < self.precision * 10.0;
+                t += distance.max(self.precision) * self.step_scale;
+            } else if distance < self.precision {
+                return Some(t);
+            } else {
+                t += distance * self.step_scale;
+            }
+            if t > self.max_distance {
+                break;
+            }
+        }
+        None
+    }
+
+    // gradient of the field by central differences
+    fn get_normal(&self, point: Point3, fallback: Vec3) -> Normal3 {
+        let h = self.precision;
+        let difference = |offset: Vec3| self.field.distance_at(point + offset) - self.field.distance_at(point - offset);
+        let gradient = Vec3::new(
+            difference(Vec3::new(h, 0.0, 0.0)),
+            difference(Vec3::new(0.0, h, 0.0)),
+            difference(Vec3::new(0.0, 0.0, h)),
+        );
+        // flat or broken fields have no gradient
+        Normal3::try_new(gradient).unwrap_or_else(|| Normal3::new(fallback))
+    }
+}
+
+impl Shape for SdfObject {
+    // only the first hit, which is all shading needs, so csg should combine fields instead
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        let length = ray.direction.length();
+        if length == 0.0 {
+            return vec![];
+        }
+        let direction = ray.direction / length;
+        match self.march(ray.start, direction) {
+            Some(t) => {
+                let point = ray.start + direction * t;
+                vec![ColoredPoint::new(point, self.color, self.get_normal(point, -direction))]
+            }
+            None => vec![],
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use super::{ColoredPoint, Ray};
+use animation::{Keyframe, Track};
+use texture::{PlanarMapping, Texture, TextureCoordinates};
+use transform::Transform;
+
+
+// anything the scene can put into the world
+pub trait Shape {
+    // normals are unit length and point outwards, points behind the ray start are skipped
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint>;
+}
+
+// places a shape into the world, many instances can share one shape
+#[derive(Clone)]
+pub struct Instance {
+    pub shape: Rc<dyn Shape>,
+    pub transform: Transform,
+}
+
+impl Instance {
+    pub fn new(shape: Rc<dyn Shape>, transform: Transform) -> Instance {
+        Instance { shape, transform }
+    }
+}
+
+impl Shape for Instance {
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        get_transformed_intersections(&*self.shape, &self.transform, ray)
+    }
+}
+
+// instance that moves between transforms at given times, e.g. while the shutter is open
+#[derive(Clone)]
+pub struct MovingInstance {
+    pub shape: Rc<dyn Shape>,
+    pub track: Track<Transform>,
+}
+
+impl MovingInstance {
+    // moves linearly between the keyframes
+    pub fn new(shape: Rc<dyn Shape>, keyframes: Vec<(f64, Transform)>) -> MovingInstance {
+        let keyframes = keyframes.into_iter().map(|(time, transform)| Keyframe::linear(time, transform)).collect();
+        MovingInstance { shape, track: Track::new(keyframes) }
+    }
+
+    pub fn from_track(shape: Rc<dyn Shape>, track: Track<Transform>) -> MovingInstance {
+        MovingInstance { shape, track }
+    }
+
+    pub fn get_transform_at(&self, time: f64) -> Transform {
+        self.track.value_at(time).unwrap_or_else(Transform::identity)
+    }
+}
+
+impl Shape for MovingInstance {
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        get_transformed_intersections(&*self.shape, &self.get_transform_at(ray.time), ray)
+    }
+}
+
+// the ray goes into object space instead of the shape going into the world
+fn get_transformed_intersections(shape: &dyn Shape, transform: &Transform, ray: Ray) -> Vec<ColoredPoint> {
+    let inverse = transform.inverse();
+    let local_ray = Ray {
+        start: inverse.apply_to_point(ray.start),
+        direction: inverse.apply_to_vector(ray.direction),
+        ..ray
+    };
+    shape.get_colored_intersections(local_ray)
+        .into_iter()
+        .filter_map(|mut point| {
+            let is_bumpy = point.shading_normal != point.normal;
+            point.point = transform.apply_to_point(point.point);
+            point.normal = transform.apply_to_normal(point.normal)?;
+            point.shading_normal = if is_bumpy { transform.apply_to_normal(point.shading_normal)? } else { point.normal };
+            Some(point)
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // the right solid is carved out of the left one
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(&self, in_left: bool, in_right: bool) -> bool {
+        match *self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+// paints any shape with a texture instead of its own colors, u and v are the x and y of the hit point
+// through the mapping, inside an instance they stay on the shape wherever it goes
+#[derive(Clone)]
+pub struct Textured {
+    pub shape: Rc<dyn Shape>,
+    pub texture: Rc<dyn Texture>,
+    pub mapping: PlanarMapping,
+}
+
+impl Textured {
+    pub fn new(shape: Rc<dyn Shape>, texture: Rc<dyn Texture>) -> Textured {
+        Textured { shape, texture, mapping: PlanarMapping::identity() }
+    }
+}
+
+impl Shape for Textured {
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        let mut points = self.shape.get_colored_intersections(ray);
+        for point in points.iter_mut() {
+            let (u, v) = self.mapping.apply(point.point.x, point.point.y);
+            let mut coordinates = TextureCoordinates::new(point.point, u, v);
+            coordinates.footprint = self.mapping.get_footprint(ray.get_footprint(point.point, point.normal));
+            point.color = self.texture.color_at(coordinates);
+        }
+        points
+    }
+}
+
+// combines two closed solids, every entry of the ray into a solid is followed by an exit
+#[derive(Clone)]
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Rc<dyn Shape>,
+    pub right: Rc<dyn Shape>,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Rc<dyn Shape>, right: Rc<dyn Shape>) -> Csg {
+        Csg { operation, left, right }
+    }
+}
+
+impl Shape for Csg {
+    // walks the crossings of both solids along the whole line and keeps those where the result changes
+    fn get_colored_intersections(&self, ray: Ray) -> Vec<ColoredPoint> {
+        let mut crossings: Vec<(f64, ColoredPoint, bool)> = get_crossings(&*self.left, ray)
+            .into_iter()
+            .map(|(t, point)| (t, point, false))
+            .chain(get_crossings(&*self.right, ray).into_iter().map(|(t, point)| (t, point, true)))
+            .collect();
+        crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        let (mut in_left, mut in_right) = (false, false);
+        let mut points = vec![];
+        for (t, mut point, is_right) in crossings {
+            let was_inside = self.operation.contains(in_left, in_right);
+            if is_right {
+                in_right = !in_right;
+            } else {
+                in_left = !in_left;
+            }
+            if t < 0.0 || self.operation.contains(in_left, in_right) == was_inside {
+                continue;
+            }
+            if is_right && self.operation == CsgOperation::Difference {
+                // the carved surface keeps the material of the right solid but faces into it
+                point.normal = -point.normal;
+                point.shading_normal = -point.shading_normal;
+            }
+            points.push(point);
+        }
+        points
+    }
+}
+
+// crossings of the whole line through the ray with their distances along it, negative behind the start
+fn get_crossings(shape: &dyn Shape, ray: Ray) -> Vec<(f64, ColoredPoint)> {
+    let length_squared = ray.direction.length_squared();
+    if length_squared == 0.0 {
+        return vec![];
+    }
+    let get_distance = |point: &ColoredPoint| (point.point - ray.start).dot(ray.direction) / length_squared;
+    let backwards = Ray { direction: -ray.direction, ..ray };
+    let mut crossings: Vec<(f64, ColoredPoint)> = shape.get_colored_intersections(ray)
+        .into_iter()
+        .map(|point| (get_distance(&point), point))
+        .collect();
+    // the start itself is already found going forwards
+    crossings.extend(shape.get_colored_intersections(backwards)
+        .into_iter()
+        .map(|point| (get_distance(&point), point))
+        .filter(|&(t, _)| t < 0.0));
+    crossings
+}
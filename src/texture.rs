@@ -0,0 +1,405 @@
+use std::f64::consts::PI;
+use std::io;
+
+use super::{Color, HdrColor};
+use math::Point3;
+use framebuffer::Framebuffer;
+use input;
+use random::Rng;
+
+
+// where a texture is looked up, u and v are surface coordinates
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TextureCoordinates {
+    pub point: Point3,
+    pub u: f64,
+    pub v: f64,
+    // approximate size of the pixel in uv units, 0.0 when unknown
+    pub footprint: f64,
+}
+
+impl TextureCoordinates {
+    pub fn new(point: Point3, u: f64, v: f64) -> TextureCoordinates {
+        TextureCoordinates { point, u, v, footprint: 0.0 }
+    }
+}
+
+pub trait Texture {
+    fn color_at(&self, coordinates: TextureCoordinates) -> Color;
+}
+
+// t = 0.0 is the first color and t = 1.0 is the second one
+pub fn mix_colors(first: Color, second: Color, t: f64) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+    Color::new(mix(first.r, second.r), mix(first.g, second.g), mix(first.b, second.b))
+}
+
+pub struct Solid {
+    pub color: Color,
+}
+
+impl Texture for Solid {
+    fn color_at(&self, _coordinates: TextureCoordinates) -> Color {
+        self.color
+    }
+}
+
+// squares of the side step in uv space
+pub struct Checker {
+    pub step: f64,
+    pub first_color: Color,
+    pub second_color: Color,
+}
+
+impl Texture for Checker {
+    fn color_at(&self, coordinates: TextureCoordinates) -> Color {
+        let x = (coordinates.u / self.step).floor().abs() as i64;
+        let y = (coordinates.v / self.step).floor().abs() as i64;
+        if (x % 2) == (y % 2) {
+            self.first_color
+        } else {
+            self.second_color
+        }
+    }
+}
+
+// stripes of the given width across u, each one runs along v
+pub struct Stripes {
+    pub width: f64,
+    pub first_color: Color,
+    pub second_color: Color,
+}
+
+impl Texture for Stripes {
+    fn color_at(&self, coordinates: TextureCoordinates) -> Color {
+        if (coordinates.u / self.width).floor() as i64 % 2 == 0 {
+            self.first_color
+        } else {
+            self.second_color
+        }
+    }
+}
+
+// first color at start, second color at end and beyond
+pub struct Gradient {
+    pub start: Point3,
+    pub end: Point3,
+    pub first_color: Color,
+    pub second_color: Color,
+}
+
+impl Texture for Gradient {
+    fn color_at(&self, coordinates: TextureCoordinates) -> Color {
+        let axis = self.end - self.start;
+        let t = (coordinates.point - self.start).dot(axis) / axis.length_squared();
+        mix_colors(self.first_color, self.second_color, t)
+    }
+}
+
+// Ken Perlin's improved noise
+pub struct PerlinNoise {
+    permutation: Vec<usize>,
+}
+
+impl PerlinNoise {
+    pub fn new(seed: u64) -> PerlinNoise {
+        let mut permutation: Vec<usize> = (0..256).collect();
+        let mut rng = Rng::new(seed);
+        for i in (1..256).rev() {
+            permutation.swap(i, (rng.next_u64() % (i as u64 + 1)) as usize);
+        }
+        let repeated = permutation.clone();
+        permutation.extend(repeated);
+        PerlinNoise { permutation }
+    }
+
+    // -1.0..1.0, 0.0 at integer points
+    pub fn noise(&self, point: Point3) -> f64 {
+        let (x, y, z) = (point.x.floor(), point.y.floor(), point.z.floor());
+        let (xi, yi, zi) = ((x as i64 & 255) as usize, (y as i64 & 255) as usize, (z as i64 & 255) as usize);
+        let (fx, fy, fz) = (point.x - x, point.y - y, point.z - z);
+        let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+        let p = &self.permutation;
+        let a = p[xi] + yi;
+        let aa = p[a] + zi;
+        let ab = p[a + 1] + zi;
+        let b = p[xi + 1] + yi;
+        let ba = p[b] + zi;
+        let bb = p[b + 1] + zi;
+        lerp(w,
+            lerp(v,
+                lerp(u, grad(p[aa], fx, fy, fz), grad(p[ba], fx - 1.0, fy, fz)),
+                lerp(u, grad(p[ab], fx, fy - 1.0, fz), grad(p[bb], fx - 1.0, fy - 1.0, fz))),
+            lerp(v,
+                lerp(u, grad(p[aa + 1], fx, fy, fz - 1.0), grad(p[ba + 1], fx - 1.0, fy, fz - 1.0)),
+                lerp(u, grad(p[ab + 1], fx, fy - 1.0, fz - 1.0), grad(p[bb + 1], fx - 1.0, fy - 1.0, fz - 1.0))))
+    }
+
+    // sum of octaves of absolute noise, every octave has twice the frequency and half the amplitude
+    pub fn turbulence(&self, point: Point3, octaves: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut scale = 1.0;
+        for _ in 0..octaves {
+            sum += self.noise((point.to_vec() * scale).to_point()).abs() / scale;
+            scale *= 2.0;
+        }
+        sum
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+// smooth noise, scale is the size of a noise cell
+pub struct Noise {
+    pub scale: f64,
+    pub first_color: Color,
+    pub second_color: Color,
+    pub perlin: PerlinNoise,
+}
+
+impl Texture for Noise {
+    fn color_at(&self, coordinates: TextureCoordinates) -> Color {
+        let value = self.perlin.noise((coordinates.point.to_vec() / self.scale).to_point());
+        mix_colors(self.first_color, self.second_color, 0.5 + 0.5 * value)
+    }
+}
+
+pub struct Turbulence {
+    pub scale: f64,
+    pub octaves: u32,
+    pub first_color: Color,
+    pub second_color: Color,
+    pub perlin: PerlinNoise,
+}
+
+impl Texture for Turbulence {
+    fn color_at(&self, coordinates: TextureCoordinates) -> Color {
+        let value = self.perlin.turbulence((coordinates.point.to_vec() / self.scale).to_point(), self.octaves);
+        mix_colors(self.first_color, self.second_color, value)
+    }
+}
+
+// veins along x, period is the distance between them
+pub struct Marble {
+    pub period: f64,
+    pub turbulence: f64,
+    pub first_color: Color,
+    pub second_color: Color,
+    pub perlin: PerlinNoise,
+}
+
+impl Texture for Marble {
+    fn color_at(&self, coordinates: TextureCoordinates) -> Color {
+        let point = (coordinates.point.to_vec() / self.period).to_point();
+        let value = (PI * (point.x + self.turbulence * self.perlin.turbulence(point, 6))).sin();
+        mix_colors(self.first_color, self.second_color, 0.5 + 0.5 * value)
+    }
+}
+
+// rings around the z axis going through center, period is the distance between them
+pub struct Wood {
+    pub center: Point3,
+    pub period: f64,
+    pub turbulence: f64,
+    pub first_color: Color,
+    pub second_color: Color,
+    pub perlin: PerlinNoise,
+}
+
+impl Texture for Wood {
+    fn color_at(&self, coordinates: TextureCoordinates) -> Color {
+        let offset = (coordinates.point - self.center) / self.period;
+        let distance = (offset.x.powi(2) + offset.y.powi(2)).sqrt();
+        let rings = distance + self.turbulence * self.perlin.turbulence(offset.to_point(), 4);
+        mix_colors(self.first_color, self.second_color, rings - rings.floor())
+    }
+}
+
+// what happens with uv coordinates outside of 0.0..1.0
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Addressing {
+    Wrap,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Filtering {
+    Nearest,
+    Bilinear,
+}
+
+// image stretched over 0.0..1.0 uv square, v goes from the bottom of the image to its top
+pub struct ImageTexture {
+    // every level is half the size of the previous one, the first one is the image itself
+    levels: Vec<Framebuffer>,
+    pub addressing: Addressing,
+    pub filtering: Filtering,
+    // blends levels according to the footprint so that distant surfaces don't flicker
+    pub mipmapping: bool,
+}
+
+impl ImageTexture {
+    // empty images can't be sampled
+    pub fn new(image: Framebuffer, addressing: Addressing, filtering: Filtering) -> io::Result<ImageTexture> {
+        if image.get_width() == 0 || image.get_height() == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "texture image is empty"));
+        }
+        let mut levels = vec![image];
+        loop {
+            let next = {
+                let last = levels.last().unwrap();
+                if last.get_width() == 1 && last.get_height() == 1 {
+                    break;
+                }
+                get_half_size_image(last)
+            };
+            levels.push(next);
+        }
+        Ok(ImageTexture { levels, addressing, filtering, mipmapping: true })
+    }
+
+    pub fn open(path: &str, addressing: Addressing, filtering: Filtering) -> io::Result<ImageTexture> {
+        ImageTexture::new(input::load_image(path)?, addressing, filtering)
+    }
+
+    pub fn get_levels(&self) -> &[Framebuffer] {
+        &self.levels
+    }
+
+    pub fn sample(&self, u: f64, v: f64, footprint: f64) -> HdrColor {
+        let image = &self.levels[0];
+        let texels = image.get_width().max(image.get_height()) as f64;
+        let level = if self.mipmapping && footprint > 0.0 {
+            (footprint * texels).log2().clamp(0.0, (self.levels.len() - 1) as f64)
+        } else {
+            0.0
+        };
+        let lower = level.floor() as usize;
+        let color = self.sample_level(lower, u, v);
+        if lower + 1 == self.levels.len() || level == lower as f64 {
+            return color;
+        }
+        let t = level - lower as f64;
+        color * (1.0 - t) + self.sample_level(lower + 1, u, v) * t
+    }
+
+    fn sample_level(&self, level: usize, u: f64, v: f64) -> HdrColor {
+        let image = &self.levels[level];
+        let width = image.get_width() as i64;
+        let height = image.get_height() as i64;
+        // texel centers are at half integer coordinates
+        let x = u * width as f64 - 0.5;
+        let y = (1.0 - v) * height as f64 - 0.5;
+        let texel = |tx: i64, ty: i64| {
+            image.get_pixel(get_address(tx, width, self.addressing), get_address(ty, height, self.addressing))
+        };
+        match self.filtering {
+            Filtering::Nearest => texel(x.round() as i64, y.round() as i64),
+            Filtering::Bilinear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1, y0) * tx;
+                let bottom = texel(x0, y0 + 1) * (1.0 - tx) + texel(x0 + 1, y0 + 1) * tx;
+                top * (1.0 - ty) + bottom * ty
+            }
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn color_at(&self, coordinates: TextureCoordinates) -> Color {
+        let color = self.sample(coordinates.u, coordinates.v, coordinates.footprint);
+        let to_component = |value: f64| (value * 255.0).round().clamp(0.0, 255.0) as u8;
+        Color::new(to_component(color.r), to_component(color.g), to_component(color.b))
+    }
+}
+
+fn get_address(texel: i64, size: i64, addressing: Addressing) -> u32 {
+    let address = match addressing {
+        Addressing::Wrap => texel.rem_euclid(size),
+        Addressing::Clamp => texel.clamp(0, size - 1),
+        Addressing::Mirror => {
+            let period = texel.rem_euclid(2 * size);
+            if period < size { period } else { 2 * size - 1 - period }
+        }
+    };
+    address as u32
+}
+
+// averages 2x2 blocks, the last row or column of odd sized images is averaged with itself
+fn get_half_size_image(image: &Framebuffer) -> Framebuffer {
+    let width = (image.get_width() / 2).max(1);
+    let height = (image.get_height() / 2).max(1);
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let x0 = (2 * x).min(image.get_width() - 1);
+            let x1 = (2 * x + 1).min(image.get_width() - 1);
+            let y0 = (2 * y).min(image.get_height() - 1);
+            let y1 = (2 * y + 1).min(image.get_height() - 1);
+            let sum = image.get_pixel(x0, y0) + image.get_pixel(x1, y0) + image.get_pixel(x0, y1) + image.get_pixel(x1, y1);
+            pixels.push(sum * 0.25);
+        }
+    }
+    Framebuffer::from_pixels(width, height, pixels)
+}
+
+// maps x and y of a plane to uv: rotates counterclockwise, then scales, then offsets
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct PlanarMapping {
+    pub scale_u: f64,
+    pub scale_v: f64,
+    pub offset_u: f64,
+    pub offset_v: f64,
+    // in radians
+    pub rotation: f64,
+}
+
+impl PlanarMapping {
+    pub fn identity() -> PlanarMapping {
+        PlanarMapping { scale_u: 1.0, scale_v: 1.0, offset_u: 0.0, offset_v: 0.0, rotation: 0.0 }
+    }
+
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let (sin, cos) = self.rotation.sin_cos();
+        let rotated_x = x * cos - y * sin;
+        let rotated_y = x * sin + y * cos;
+        (rotated_x * self.scale_u + self.offset_u, rotated_y * self.scale_v + self.offset_v)
+    }
+
+    // converts the size in plane units to uv units
+    pub fn get_footprint(&self, footprint: f64) -> f64 {
+        footprint * self.scale_u.abs().max(self.scale_v.abs())
+    }
+}
+
+// u goes around z axis and v goes from the bottom to the top, both are 0.0..1.0
+pub fn get_spherical_coordinates(point: Point3, center: Point3) -> (f64, f64) {
+    let offset = point - center;
+    let radius = offset.length();
+    let u = 0.5 + offset.y.atan2(offset.x) / (2.0 * PI);
+    let v = 0.5 + (offset.z / radius).clamp(-1.0, 1.0).asin() / PI;
+    (u, v)
+}
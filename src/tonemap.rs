@@ -0,0 +1,58 @@
+use super::{Color, HdrColor};
+
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ToneMapping {
+    Clamp,
+    Reinhard,
+    // radiance at white_point and above maps to pure white
+    ExtendedReinhard { white_point: f64 },
+    // Krzysztof Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+pub const TONE_MAPPING_NAMES: [&str; 4] = ["clamp", "reinhard", "extended-reinhard", "aces"];
+
+impl ToneMapping {
+    pub fn from_name(name: &str, white_point: f64) -> Option<ToneMapping> {
+        match name {
+            "clamp" => Some(ToneMapping::Clamp),
+            "reinhard" => Some(ToneMapping::Reinhard),
+            "extended-reinhard" => Some(ToneMapping::ExtendedReinhard { white_point }),
+            "aces" => Some(ToneMapping::Aces),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, radiance: HdrColor, exposure: f64) -> Color {
+        let exposed = radiance * get_exposure_scale(exposure);
+        Color::new(
+            to_color_component(self.map_component(exposed.r)),
+            to_color_component(self.map_component(exposed.g)),
+            to_color_component(self.map_component(exposed.b)),
+        )
+    }
+
+    pub fn map_component(&self, value: f64) -> f64 {
+        let value = value.max(0.0);
+        let mapped = match *self {
+            ToneMapping::Clamp => value,
+            ToneMapping::Reinhard => value / (1.0 + value),
+            ToneMapping::ExtendedReinhard { white_point } => {
+                value * (1.0 + value / white_point.powi(2)) / (1.0 + value)
+            }
+            ToneMapping::Aces => {
+                (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+            }
+        };
+        mapped.min(1.0)
+    }
+}
+
+pub fn get_exposure_scale(exposure: f64) -> f64 {
+    2.0_f64.powf(exposure)
+}
+
+fn to_color_component(value: f64) -> u8 {
+    (value * 255.0).min(255.0) as u8
+}
@@ -0,0 +1,106 @@
+use math::{Mat4, Normal3, Point3, Vec3};
+
+
+// affine transform from object space to world space, keeps its inverse along
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform { matrix: Mat4::identity(), inverse: Mat4::identity() }
+    }
+
+    // None when the matrix can't be inverted
+    pub fn from_matrix(matrix: Mat4) -> Option<Transform> {
+        matrix.inverse().map(|inverse| Transform { matrix, inverse })
+    }
+
+    pub fn translation(offset: Vec3) -> Transform {
+        let mut matrix = Mat4::identity();
+        let mut inverse = Mat4::identity();
+        for (i, value) in [offset.x, offset.y, offset.z].iter().enumerate() {
+            matrix.rows[i][3] = *value;
+            inverse.rows[i][3] = -*value;
+        }
+        Transform { matrix, inverse }
+    }
+
+    // negative factors mirror, None for factors that can't be undone, like zero
+    pub fn scaling(x: f64, y: f64, z: f64) -> Option<Transform> {
+        if [x, y, z].iter().any(|value| !value.is_finite() || !(1.0 / value).is_finite()) {
+            return None;
+        }
+        let mut matrix = Mat4::identity();
+        let mut inverse = Mat4::identity();
+        for (i, value) in [x, y, z].iter().enumerate() {
+            matrix.rows[i][i] = *value;
+            inverse.rows[i][i] = 1.0 / *value;
+        }
+        Some(Transform { matrix, inverse })
+    }
+
+    // counterclockwise when the axis points at the viewer, angle is in radians,
+    // None without a direction to turn around or a finite angle
+    pub fn rotation(axis: Vec3, angle: f64) -> Option<Transform> {
+        let length = axis.length();
+        if !(length > 0.0 && length.is_finite() && angle.is_finite()) {
+            return None;
+        }
+        let Vec3 { x, y, z } = axis / length;
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+        let matrix = Mat4::new([
+            [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y, 0.0],
+            [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x, 0.0],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        // rotations are orthogonal
+        Some(Transform { matrix, inverse: matrix.transpose() })
+    }
+
+    // applies self first and other after it
+    pub fn then(&self, other: &Transform) -> Transform {
+        Transform {
+            matrix: other.matrix * self.matrix,
+            inverse: self.inverse * other.inverse,
+        }
+    }
+
+    // blends the matrices, which is exact for translations and scaling and shrinks
+    // big rotations on the way, the start is kept when the blend can't be inverted
+    pub fn interpolate(&self, other: &Transform, t: f64) -> Transform {
+        let mut rows = self.matrix.rows;
+        for (row, other_row) in rows.iter_mut().zip(other.matrix.rows.iter()) {
+            for (value, other_value) in row.iter_mut().zip(other_row.iter()) {
+                *value += (other_value - *value) * t;
+            }
+        }
+        Transform::from_matrix(Mat4::new(rows)).unwrap_or(*self)
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    pub fn get_matrix(&self) -> Mat4 {
+        self.matrix
+    }
+
+    pub fn apply_to_point(&self, point: Point3) -> Point3 {
+        self.matrix.transform_point(point)
+    }
+
+    pub fn apply_to_vector(&self, vector: Vec3) -> Vec3 {
+        self.matrix.transform_vector(vector)
+    }
+
+    // normals stay perpendicular to the surface under non uniform scaling,
+    // None when the transform flattens the space
+    pub fn apply_to_normal(&self, normal: Normal3) -> Option<Normal3> {
+        Normal3::try_new(self.inverse.transpose().transform_vector(*normal))
+    }
+}
@@ -1,7 +1,22 @@
 extern crate raytracer;
 
-use raytracer::{Floor, Point, Plane, Ray, Sphere, Scene, Color, BLACK, WHITE};
+use raytracer::{AnimationError, Aov, Background, BumpMap, Camera, Color, Csg, CsgOperation, Floor, Framebuffer, HdrColor, Instance, Interpolation, Keyframe, Mat4, MovingInstance, Normal3, Plane, Point3, Projection, Ray, Region, Scene, SceneAnimation, Shape, Sphere, Texture, TextureCoordinates, Textured, ToneMapping, Track, Transform, Vec3, BLACK, WHITE};
+use raytracer::animation::{ObjectAnimation, SphereAnimation};
+use raytracer::background::{EnvironmentMap, Gradient as SkyGradient, SunSky, Uniform};
+use raytracer::blob::{Blob, BlobComponent};
+use raytracer::bump::{self, TangentFrame};
+use raytracer::denoise::{self, DenoiseOptions};
+use raytracer::input;
+use raytracer::output::{self, ExrPrecision, ImageFormat};
+use raytracer::primitives::{Cone, Cuboid, Cylinder, Disk, Torus};
+use raytracer::progressive::Accumulation;
+use raytracer::random::Rng;
+use raytracer::sdf::{self, Ball, Block, DistanceField, Mandelbulb, Repetition, SdfObject, SmoothUnion, Twist};
+use raytracer::texture::{self, Addressing, Checker, Filtering, Gradient, ImageTexture, Marble, PerlinNoise, PlanarMapping, Solid, Stripes};
+use std::env;
 use std::f64;
+use std::fs;
+use std::rc::Rc;
 
 macro_rules! assert_close_colors {
     ($color_a:expr, $color_b:expr, $epsilon:expr) => {{
@@ -9,6 +24,18 @@ macro_rules! assert_close_colors {
     }};
 }
 
+macro_rules! assert_close_hdr_colors {
+    ($color_a:expr, $color_b:expr, $epsilon:expr) => {{
+        assert!(distance_between_hdr_colors($color_a, $color_b) < $epsilon, "not close colors {}, {}", $color_a, $color_b);
+    }};
+}
+
+macro_rules! assert_close_vectors {
+    ($vector_a:expr, $vector_b:expr, $epsilon:expr) => {{
+        assert!(($vector_a - $vector_b).length() < $epsilon, "not close vectors {}, {}", $vector_a, $vector_b);
+    }};
+}
+
 macro_rules! assert_close_points {
     ($point_a:expr, $point_b:expr, $epsilon:expr) => {{
         assert!(raytracer::get_distance($point_a, $point_b) < $epsilon, "not close points {}, {}", $point_a, $point_b);
@@ -19,46 +46,368 @@ macro_rules! assert_close_points {
 #[test]
 fn floor_color_at() {
     let floor = Floor::new(5.0, BLACK, WHITE);
-    let black_point = Point::new(0.1, 0.1, 0.0);
+    let black_point = Point3::new(0.1, 0.1, 0.0);
     assert_eq!(floor.color_at(black_point), BLACK);
-    let white_point = Point::new(5.1, 0.1, 0.0);
+    let white_point = Point3::new(5.1, 0.1, 0.0);
     assert_eq!(floor.color_at(white_point), WHITE);
 
-    let another_black_point = Point::new(5.1, -0.1, 0.0);
+    let another_black_point = Point3::new(5.1, -0.1, 0.0);
     assert_eq!(floor.color_at(another_black_point), BLACK);
-    let another_white_point = Point::new(-5.1, -0.1, 0.0);
+    let another_white_point = Point3::new(-5.1, -0.1, 0.0);
     assert_eq!(floor.color_at(another_white_point), WHITE);
 }
 
+#[test]
+fn floor_with_texture() {
+    let floor = Floor::with_texture(Rc::new(Stripes { width: 2.0, first_color: BLACK, second_color: WHITE }));
+    assert_eq!(floor.color_at(Point3::new(1.0, 7.0, 0.0)), BLACK);
+    assert_eq!(floor.color_at(Point3::new(3.0, 7.0, 0.0)), WHITE);
+    assert_eq!(floor.color_at(Point3::new(-1.0, 7.0, 0.0)), WHITE);
+}
+
+#[test]
+fn checker_texture() {
+    let checker = Checker { step: 0.5, first_color: BLACK, second_color: WHITE };
+    assert_eq!(checker.color_at(TextureCoordinates::new(Point3::new(0.0, 0.0, 0.0), 0.25, 0.25)), BLACK);
+    assert_eq!(checker.color_at(TextureCoordinates::new(Point3::new(0.0, 0.0, 0.0), 0.75, 0.25)), WHITE);
+}
+
+#[test]
+fn gradient_texture() {
+    let gradient = Gradient {
+        start: Point3::new(0.0, 0.0, 0.0),
+        end: Point3::new(0.0, 0.0, 10.0),
+        first_color: Color::new(0, 0, 0),
+        second_color: Color::new(200, 100, 0),
+    };
+    let color_at = |z| gradient.color_at(TextureCoordinates::new(Point3::new(5.0, 5.0, z), 0.0, 0.0));
+    assert_eq!(color_at(5.0), Color::new(100, 50, 0));
+    assert_eq!(color_at(-5.0), Color::new(0, 0, 0));
+    assert_eq!(color_at(20.0), Color::new(200, 100, 0));
+}
+
+fn get_image_texture(addressing: Addressing, filtering: Filtering) -> ImageTexture {
+    // black and white texels on the top row, red and blue on the bottom one
+    let pixels = vec![
+        HdrColor::new(0.0, 0.0, 0.0), HdrColor::new(1.0, 1.0, 1.0),
+        HdrColor::new(1.0, 0.0, 0.0), HdrColor::new(0.0, 0.0, 1.0),
+    ];
+    ImageTexture::new(Framebuffer::from_pixels(2, 2, pixels), addressing, filtering).unwrap()
+}
+
+#[test]
+fn load_hdr_image() {
+    let path = env::temp_dir().join("raytracer_test_load.hdr");
+    let path = path.to_str().unwrap();
+    // wide enough to be run length encoded
+    let pixels: Vec<HdrColor> = (0..20).map(|i| HdrColor::new(i as f64, 0.5, 0.0)).collect();
+    for width in [10, 5].iter() {
+        output::save_hdr_image(path, ImageFormat::Hdr, *width, 20 / width, &pixels).unwrap();
+        let image = input::load_image(path).unwrap();
+        assert_eq!((image.get_width(), image.get_height()), (*width, 20 / width));
+        assert_eq!(image.get_pixels(), &pixels[..]);
+    }
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn image_texture_filtering() {
+    let nearest = get_image_texture(Addressing::Clamp, Filtering::Nearest);
+    assert_close_hdr_colors!(nearest.sample(0.1, 0.9, 0.0), HdrColor::new(0.0, 0.0, 0.0), 1e-9);
+    assert_close_hdr_colors!(nearest.sample(0.9, 0.1, 0.0), HdrColor::new(0.0, 0.0, 1.0), 1e-9);
+    let bilinear = get_image_texture(Addressing::Clamp, Filtering::Bilinear);
+    assert_close_hdr_colors!(bilinear.sample(0.5, 0.75, 0.0), HdrColor::new(0.5, 0.5, 0.5), 1e-9);
+    assert_close_hdr_colors!(bilinear.sample(0.5, 0.5, 0.0), HdrColor::new(0.5, 0.25, 0.5), 1e-9);
+    assert_eq!(bilinear.color_at(TextureCoordinates::new(Point3::new(0.0, 0.0, 0.0), 0.5, 0.75)), Color::new(128, 128, 128));
+}
+
+#[test]
+fn image_texture_addressing() {
+    let wrap = get_image_texture(Addressing::Wrap, Filtering::Nearest);
+    let clamp = get_image_texture(Addressing::Clamp, Filtering::Nearest);
+    let mirror = get_image_texture(Addressing::Mirror, Filtering::Nearest);
+    // first texel to the right of the image
+    assert_close_hdr_colors!(wrap.sample(1.25, 0.75, 0.0), HdrColor::new(0.0, 0.0, 0.0), 1e-9);
+    assert_close_hdr_colors!(clamp.sample(1.25, 0.75, 0.0), HdrColor::new(1.0, 1.0, 1.0), 1e-9);
+    assert_close_hdr_colors!(mirror.sample(1.25, 0.75, 0.0), HdrColor::new(1.0, 1.0, 1.0), 1e-9);
+    // second texel to the right of the image
+    assert_close_hdr_colors!(wrap.sample(1.75, 0.75, 0.0), HdrColor::new(1.0, 1.0, 1.0), 1e-9);
+    assert_close_hdr_colors!(clamp.sample(1.75, 0.75, 0.0), HdrColor::new(1.0, 1.0, 1.0), 1e-9);
+    assert_close_hdr_colors!(mirror.sample(1.75, 0.75, 0.0), HdrColor::new(0.0, 0.0, 0.0), 1e-9);
+}
+
+#[test]
+fn image_texture_mipmaps() {
+    let mut texture = get_image_texture(Addressing::Wrap, Filtering::Nearest);
+    assert_eq!(texture.get_levels().len(), 2);
+    let average = HdrColor::new(0.5, 0.25, 0.5);
+    assert_close_hdr_colors!(texture.sample(0.1, 0.9, 1.0), average, 1e-9);
+    // halfway between the levels
+    assert_close_hdr_colors!(texture.sample(0.1, 0.9, 2.0_f64.sqrt() / 2.0), average * 0.5, 1e-9);
+    texture.mipmapping = false;
+    assert_close_hdr_colors!(texture.sample(0.1, 0.9, 1.0), HdrColor::new(0.0, 0.0, 0.0), 1e-9);
+}
+
+#[test]
+fn planar_mapping() {
+    let mapping = PlanarMapping { scale_u: 2.0, scale_v: 0.5, offset_u: 0.25, offset_v: 0.0, rotation: f64::consts::PI / 2.0 };
+    let (u, v) = mapping.apply(1.0, 0.0);
+    assert!((u - 0.25).abs() < 1e-9 && (v - 0.5).abs() < 1e-9, "{} {}", u, v);
+    assert_eq!(mapping.get_footprint(0.5), 1.0);
+    assert_eq!(PlanarMapping::identity().apply(3.0, -4.0), (3.0, -4.0));
+}
+
+#[test]
+fn load_image() {
+    let pixels = vec![BLACK, WHITE, Color::new(255, 0, 0), Color::new(0, 0, 255), WHITE, BLACK];
+    for format in [ImageFormat::Png, ImageFormat::Bmp, ImageFormat::Ppm].iter() {
+        let path = env::temp_dir().join(format!("raytracer_test_load.{:?}", format).to_lowercase());
+        let path = path.to_str().unwrap();
+        output::save_image(path, *format, 3, 2, &pixels).unwrap();
+        let image = input::load_image(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!((image.get_width(), image.get_height()), (3, 2));
+        assert_eq!(image.get_pixel(2, 0), HdrColor::new(1.0, 0.0, 0.0));
+        assert_eq!(image.get_pixel(0, 1), HdrColor::new(0.0, 0.0, 1.0));
+    }
+    assert!(input::load_image("missing.png").is_err());
+}
+
+#[test]
+fn malformed_image_headers() {
+    let mut negative_width_bmp = vec![0u8; 54];
+    negative_width_bmp[..2].copy_from_slice(b"BM");
+    negative_width_bmp[10..14].copy_from_slice(&54u32.to_le_bytes());
+    negative_width_bmp[14..18].copy_from_slice(&40u32.to_le_bytes());
+    negative_width_bmp[18..22].copy_from_slice(&(-3i32).to_le_bytes());
+    negative_width_bmp[22..26].copy_from_slice(&2i32.to_le_bytes());
+    negative_width_bmp[26..28].copy_from_slice(&1u16.to_le_bytes());
+    negative_width_bmp[28..30].copy_from_slice(&24u16.to_le_bytes());
+    let files: Vec<(&str, Vec<u8>)> = vec![
+        ("bmp", negative_width_bmp),
+        ("ppm", b"P6\n4294967295 4294967295\n255\n".to_vec()),
+        ("ppm", b"P6\n0 0\n255\n".to_vec()),
+        ("pfm", b"PF\n65536 65536\n-1.0\n".to_vec()),
+        ("hdr", b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 4294967295 +X 4294967295\n".to_vec()),
+    ];
+    for (index, (extension, bytes)) in files.iter().enumerate() {
+        let path = env::temp_dir().join(format!("raytracer_test_malformed_{}.{}", index, extension));
+        let path = path.to_str().unwrap();
+        fs::write(path, bytes).unwrap();
+        let error = input::load_image(path).err();
+        fs::remove_file(path).unwrap();
+        assert_eq!(error.map(|error| error.kind()), Some(std::io::ErrorKind::InvalidData), "{}", index);
+    }
+    let empty = Framebuffer::new(0, 0);
+    assert!(ImageTexture::new(empty.clone(), Addressing::Wrap, Filtering::Nearest).is_err());
+    assert!(EnvironmentMap::new(empty).is_err());
+}
+
+#[test]
+fn sky_gradient() {
+    let sky = SkyGradient {
+        zenith_color: HdrColor::new(0.0, 0.0, 1.0),
+        horizon_color: HdrColor::new(1.0, 1.0, 1.0),
+        ground_color: HdrColor::new(0.2, 0.2, 0.2),
+    };
+    assert_close_hdr_colors!(sky.radiance_at(Vec3::new(0.0, 0.0, 5.0)), HdrColor::new(0.0, 0.0, 1.0), 1e-9);
+    assert_close_hdr_colors!(sky.radiance_at(Vec3::new(-1.0, 0.0, 0.0)), HdrColor::new(1.0, 1.0, 1.0), 1e-9);
+    assert_close_hdr_colors!(sky.radiance_at(Vec3::new(-1.0, 0.0, -1.0)), HdrColor::new(0.2, 0.2, 0.2), 1e-9);
+    let sin_30 = sky.radiance_at(Vec3::new(-3.0_f64.sqrt(), 0.0, 1.0));
+    assert_close_hdr_colors!(sin_30, HdrColor::new(0.5, 0.5, 1.0), 1e-9);
+}
+
+#[test]
+fn sun_sky() {
+    let sky = SunSky {
+        sun_direction: Vec3::new(-1.0, 0.0, 1.0),
+        sun_color: HdrColor::new(50.0, 45.0, 40.0),
+        sun_radius: 0.01,
+        zenith_color: HdrColor::new(0.1, 0.2, 0.6),
+        horizon_color: HdrColor::new(0.6, 0.7, 0.9),
+        ground_color: HdrColor::new(0.1, 0.1, 0.1),
+    };
+    assert_eq!(sky.radiance_at(Vec3::new(-2.0, 0.0, 2.0)), sky.sun_color);
+    let near_sun = sky.radiance_at(Vec3::new(-1.0, 0.1, 1.0));
+    let away_from_sun = sky.radiance_at(Vec3::new(1.0, 0.1, 1.0));
+    assert!(near_sun.b > away_from_sun.b && near_sun.b < sky.sun_color.b);
+    assert_eq!(sky.radiance_at(Vec3::new(0.0, 1.0, -0.5)), sky.ground_color);
+}
+
+#[test]
+fn environment_map() {
+    // sky and ground rows, the left half of the image is to the left of the default camera
+    let mut pixels: Vec<HdrColor> = (0..4).map(|x| HdrColor::new(x as f64, 1.0, 0.0)).collect();
+    pixels.extend((0..4).map(|x| HdrColor::new(x as f64, 0.0, 0.0)));
+    let mut environment = EnvironmentMap::new(Framebuffer::from_pixels(4, 2, pixels)).unwrap();
+    // front is between the second and the third column
+    assert_close_hdr_colors!(environment.radiance_at(Vec3::new(-1.0, 0.0, 10.0)), HdrColor::new(1.5, 1.0, 0.0), 1e-9);
+    assert_close_hdr_colors!(environment.radiance_at(Vec3::new(0.0, 1.0, -10.0)), HdrColor::new(0.5, 0.0, 0.0), 1e-9);
+    assert_close_hdr_colors!(environment.radiance_at(Vec3::new(0.0, -1.0, 0.0)), HdrColor::new(2.5, 0.5, 0.0), 1e-9);
+    // behind wraps around between the last and the first column
+    assert_close_hdr_colors!(environment.radiance_at(Vec3::new(1.0, 0.0, 0.0)), HdrColor::new(1.5, 0.5, 0.0), 1e-9);
+    environment.rotation = f64::consts::PI / 2.0;
+    environment.intensity = 2.0;
+    assert_close_hdr_colors!(environment.radiance_at(Vec3::new(-1.0, 0.0, 0.0)), HdrColor::new(5.0, 1.0, 0.0), 1e-9);
+}
+
+#[test]
+fn mirror_reflects_background() {
+    let mirror = Sphere {
+        center: Point3::new(-100.0, 0.0, 50.0),
+        radius: 40.0,
+        color: BLACK,
+        is_mirror: true,
+        ..make_sphere()
+    };
+    let sky = SkyGradient {
+        zenith_color: HdrColor::new(0.0, 0.0, 1.0),
+        horizon_color: HdrColor::new(1.0, 1.0, 1.0),
+        ground_color: HdrColor::new(0.0, 0.0, 0.0),
+    };
+    let sky_radiance = sky.radiance_at(Vec3::new(-100.0, 0.0, 150.0));
+    let scene = Scene {
+        background: Rc::new(sky),
+        spheres: vec![mirror],
+        eye: Point3::new(100.0, 0.0, 50.0),
+        ..make_scene()
+    };
+    assert_eq!(scene.sample_at(0, 200).radiance, sky_radiance);
+    // the upper part of the mirror reflects the sky above the horizon
+    let reflection = scene.sample_at(0, 60);
+    assert_eq!(reflection.object_id, 0);
+    assert!(reflection.radiance.b == 1.0 && reflection.radiance.r > 0.0 && reflection.radiance.r < 1.0, "{}", reflection.radiance);
+}
+
+// gray that gets brighter along u
+struct Ramp;
+
+impl Texture for Ramp {
+    fn color_at(&self, coordinates: TextureCoordinates) -> Color {
+        let gray = (coordinates.u * 255.0).round() as u8;
+        Color::new(gray, gray, gray)
+    }
+}
+
+#[test]
+fn tangent_frame() {
+    let frame = TangentFrame::new(Vec3::new(1.0, 0.0, 1.0), Normal3::new(Vec3::new(0.0, 0.0, 2.0)));
+    assert_eq!(frame.tangent, Vec3::new(1.0, 0.0, 0.0));
+    assert_eq!(frame.bitangent, Vec3::new(0.0, 1.0, 0.0));
+    assert_eq!(frame.normal, Normal3::new(Vec3::new(0.0, 0.0, 1.0)));
+    // at the poles of a sphere
+    let frame = TangentFrame::new(Vec3::new(0.0, 0.0, 0.0), Normal3::new(Vec3::new(0.0, 0.0, 1.0)));
+    assert_eq!(frame.tangent, Vec3::new(1.0, 0.0, 0.0));
+}
+
+#[test]
+fn bump_maps() {
+    let frame = TangentFrame::new(Vec3::new(1.0, 0.0, 0.0), Normal3::new(Vec3::new(0.0, 0.0, 1.0)));
+    let mut coordinates = TextureCoordinates::new(Point3::new(0.0, 0.0, 0.0), 0.5, 0.5);
+    coordinates.footprint = 0.1;
+    // height grows by one uv unit per uv unit along u
+    let height = BumpMap::Height { texture: Rc::new(Ramp), strength: 1.0 };
+    let half = 0.5_f64.sqrt();
+    assert_close_vectors!(height.get_normal(coordinates, &frame).to_vec(), Vec3::new(-half, 0.0, half), 1e-9);
+    let flat = BumpMap::Normal { texture: Rc::new(Solid { color: Color::new(128, 128, 255) }), strength: 1.0 };
+    assert_close_vectors!(flat.get_normal(coordinates, &frame).to_vec(), Vec3::new(0.0, 0.0, 1.0), 0.01);
+    let tilted = BumpMap::Normal { texture: Rc::new(Solid { color: Color::new(128, 255, 128) }), strength: 2.0 };
+    let normal = tilted.get_normal(coordinates, &frame);
+    assert!(normal.y > 0.99 && normal.z > 0.0, "{}", normal);
+    // too strong to have a normal, so the surface stays flat
+    let endless = BumpMap::Height { texture: Rc::new(Ramp), strength: f64::INFINITY };
+    assert_eq!(endless.get_normal(coordinates, &frame), frame.normal);
+    let endless = BumpMap::Normal { texture: Rc::new(Solid { color: Color::new(255, 128, 128) }), strength: f64::INFINITY };
+    assert_eq!(endless.get_normal(coordinates, &frame), frame.normal);
+}
+
+#[test]
+fn bumpy_floor() {
+    let normal = Normal3::new(Vec3::new(0.0, 0.0, 1.0));
+    let to_light = Vec3::new(1.0, 0.0, 1.0);
+    assert_eq!(bump::get_lighting_factor(normal, normal, to_light), 1.0);
+    assert!(bump::get_lighting_factor(Normal3::new(Vec3::new(0.6, 0.0, 0.8)), normal, to_light) > 1.0);
+    assert!(bump::get_lighting_factor(Normal3::new(Vec3::new(-0.6, 0.0, 0.8)), normal, to_light) < 1.0);
+    let make_bumpy_scene = |bump: Option<BumpMap>| {
+        let mut floor = Floor::new(32.0, WHITE, WHITE);
+        floor.set_bump_map(bump);
+        Scene { floor: Some(floor), ..make_scene() }
+    };
+    // tilted towards -x where the light is
+    let towards_light = BumpMap::Normal { texture: Rc::new(Solid { color: Color::new(0, 128, 200) }), strength: 1.0 };
+    let flat = make_bumpy_scene(None).sample_at(2, 1);
+    let bumpy = make_bumpy_scene(Some(towards_light)).sample_at(2, 1);
+    assert!(bumpy.radiance.r > flat.radiance.r);
+    assert!(bumpy.normal.x < 0.0 && flat.normal == normal.to_vec());
+}
+
+#[test]
+fn perlin_noise() {
+    let perlin = PerlinNoise::new(42);
+    assert_eq!(perlin.noise(Point3::new(3.0, -2.0, 7.0)), 0.0);
+    let value = perlin.noise(Point3::new(3.3, -2.7, 7.1));
+    assert!(value > -1.0 && value < 1.0 && value != 0.0);
+    // deterministic and continuous
+    assert_eq!(value, PerlinNoise::new(42).noise(Point3::new(3.3, -2.7, 7.1)));
+    assert!((value - perlin.noise(Point3::new(3.3001, -2.7, 7.1))).abs() < 0.01);
+    assert!(perlin.turbulence(Point3::new(3.3, -2.7, 7.1), 4) >= value.abs());
+}
+
+#[test]
+fn textured_sphere() {
+    let marble = Marble {
+        period: 10.0,
+        turbulence: 2.0,
+        first_color: BLACK,
+        second_color: WHITE,
+        perlin: PerlinNoise::new(1),
+    };
+    let sphere = Sphere { texture: Some(Rc::new(marble)), ..make_sphere() };
+    let point = Point3::new(0.0, 0.0, 1.0);
+    let (u, v) = texture::get_spherical_coordinates(point, sphere.center);
+    assert!(raytracer::are_close(v, 1.0));
+    assert!((0.0..=1.0).contains(&u));
+    let expected = sphere.texture.as_ref().unwrap().color_at(TextureCoordinates::new(point, u, v));
+    assert_eq!(sphere.color_at(point), expected);
+}
+
 #[test]
 fn ray_plane_intersection() {
     let ray = Ray::new(
-        Point::new(-1.0, -1.0, -1.0),
-        Point::new(1.0, 1.0, 1.0),
+        Point3::new(-1.0, -1.0, -1.0),
+        Vec3::new(1.0, 1.0, 1.0),
     );
-    let plane = Plane::new(0.0, 0.0, 1.0, 0.0);
+    let plane = Plane::new(0.0, 0.0, 1.0, 0.0).unwrap();
     let points = plane.get_intersections(ray);
     assert_eq!(1, points.len());
-    assert_eq!(points[0], Point::new(0.0, 0.0, 0.0))
+    assert_eq!(points[0], Point3::new(0.0, 0.0, 0.0))
 }
 
 
 #[test]
 fn ray_plane_no_intersection() {
     let ray = Ray::new(
-        Point::new(1.0, 1.0, 1.0),
-        Point::new(1.0, 1.0, 1.0));
-    let plane = Plane::new(0.0, 0.0, 1.0, 0.0);
+        Point3::new(1.0, 1.0, 1.0),
+        Vec3::new(1.0, 1.0, 1.0));
+    let plane = Plane::new(0.0, 0.0, 1.0, 0.0).unwrap();
     let points = plane.get_intersections(ray);
     assert_eq!(0, points.len());
 }
 
+#[test]
+fn plane_without_normal() {
+    assert!(Plane::new(0.0, 0.0, 0.0, 1.0).is_none());
+    assert!(Plane::new(0.0, f64::NAN, 1.0, 0.0).is_none());
+    let plane = Plane::from_point_and_normal(Point3::new(0.0, 0.0, 2.0), Normal3::new(Vec3::new(0.0, 0.0, 1.0)));
+    assert_eq!(plane.get_normal(), Normal3::new(Vec3::new(0.0, 0.0, 1.0)));
+}
+
 #[test]
 fn distance() {
-    let origin = Point::new(0.0, 0.0, 0.0);
-    let point = Point::new(1.0, 3.0, 5.0);
+    let origin = Point3::new(0.0, 0.0, 0.0);
+    let point = Point3::new(1.0, 3.0, 5.0);
     let distance = raytracer::get_distance(origin, point);
-    assert!(raytracer::are_close(distance, (35.0 as f64).sqrt()));
+    assert!(raytracer::are_close(distance, 35.0_f64.sqrt()));
 }
 
 #[test]
@@ -78,33 +427,47 @@ fn intensify_very_bright() {
     assert_eq!(intensified, expected);
 }
 
+#[test]
+fn clamp_tone_mapping() {
+    let radiance = HdrColor::new(0.5, 1.0, 3.0);
+    assert_eq!(ToneMapping::Clamp.apply(radiance, 0.0), Color::new(127, 255, 255));
+    // one stop down halves the radiance
+    assert_eq!(ToneMapping::Clamp.apply(radiance, -1.0), Color::new(63, 127, 255));
+}
+
+#[test]
+fn reinhard_tone_mapping() {
+    assert!(raytracer::are_close(ToneMapping::Reinhard.map_component(1.0), 0.5));
+    assert!(raytracer::are_close(ToneMapping::Reinhard.map_component(3.0), 0.75));
+    let extended = ToneMapping::ExtendedReinhard { white_point: 4.0 };
+    assert!(raytracer::are_close(extended.map_component(4.0), 1.0));
+    assert!(extended.map_component(1.0) > ToneMapping::Reinhard.map_component(1.0));
+}
+
+#[test]
+fn aces_tone_mapping() {
+    assert!(raytracer::are_close(ToneMapping::Aces.map_component(0.0), 0.0));
+    assert!(raytracer::are_close(ToneMapping::Aces.map_component(100.0), 1.0));
+    assert!(ToneMapping::Aces.map_component(0.5) < ToneMapping::Aces.map_component(1.0));
+}
+
 #[test]
 fn ray_sphere_intersection() {
     let ray = Ray::new(
-        Point::new(0.0, 0.0, 0.0),
-        Point::new(1.0, 0.0, 0.0));
-    let sphere = Sphere {
-        center: Point::new(0.0, 0.0, 0.0),
-        radius: 1.0,
-        color: BLACK,
-        is_mirror: false,
-    };
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(1.0, 0.0, 0.0));
+    let sphere = Sphere { color: BLACK, ..make_sphere() };
     let points = sphere.get_intersections(ray);
     assert_eq!(1, points.len());
-    assert!(raytracer::are_close_points(points[0], Point::new(1.0, 0.0, 0.0)));
+    assert!(raytracer::are_close_points(points[0], Point3::new(1.0, 0.0, 0.0)));
 }
 
 #[test]
 fn ray_sphere_no_intersection() {
     let ray = Ray::new(
-        Point::new(0.0, 0.0, 0.0),
-        Point::new(1.0, 0.0, 0.0));
-    let sphere = Sphere {
-        center: Point::new(10.0, 10.0, 10.0),
-        radius: 1.0,
-        color: WHITE,
-        is_mirror: false,
-    };
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(1.0, 0.0, 0.0));
+    let sphere = Sphere { center: Point3::new(10.0, 10.0, 10.0), color: WHITE, ..make_sphere() };
     let points = sphere.get_intersections(ray);
     assert_eq!(0, points.len());
 }
@@ -121,41 +484,36 @@ fn quadratic_equation() {
 
 #[test]
 fn get_closest_point() {
-    let point = Point::new(0.0, 0.0, 0.0);
-    let a = Point::new(1.0, 1.0, 1.0);
-    let b = Point::new(2.0, 2.0, 2.0);
-    match raytracer::get_closest_point(point, &vec![a, b]) {
+    let point = Point3::new(0.0, 0.0, 0.0);
+    let a = Point3::new(1.0, 1.0, 1.0);
+    let b = Point3::new(2.0, 2.0, 2.0);
+    match raytracer::get_closest_point(point, &[a, b]) {
         Some(actual_point) => assert_eq!(actual_point, a),
-        None => assert!(false),
+        None => panic!("no closest point"),
     }
 }
 
 #[test]
 fn get_no_closest_point() {
-    let point = Point::new(0.0, 0.0, 0.0);
-    let no_points: Vec<Point> = vec![];
-    match raytracer::get_closest_point(point, &no_points) {
-        None => assert!(true),
-        Some(_) => assert!(false),
-    }
+    let point = Point3::new(0.0, 0.0, 0.0);
+    let no_points: Vec<Point3> = vec![];
+    assert!(raytracer::get_closest_point(point, &no_points).is_none());
 }
 
 #[test]
 fn screen_color() {
     let green = Color::new(0, 150, 0);
     let sphere = Sphere {
-        center: Point::new(-90.0, 10.0, 10.0),
+        center: Point3::new(-90.0, 10.0, 10.0),
         radius: 10.0,
         color: green,
-        is_mirror: false,
+        ..make_sphere()
     };
     let sky = Color::new(0, 0, 180);
     let scene = Scene {
-        floor: Floor::new(32.0, BLACK, WHITE),
-        light_source: Point::new(-200.0, 10.0, 200.0),
-        sky_color: sky,
+        background: Rc::new(Uniform { color: HdrColor::from_color(sky) }),
         spheres: vec![sphere],
-        eye: Point::new(30.0, 30.0, 30.0),
+        ..make_scene()
     };
     assert_close_colors!(scene.color_at(255, 255), sky, 0.001);
     // white floor
@@ -175,37 +533,1134 @@ fn screen_color() {
     assert_close_colors!(scene.color_at(25, 25), raytracer::intensify(scene.spheres[0].color, 1.493329386), 0.001);
 }
 
+#[test]
+fn render_into_framebuffer() {
+    let scene = Scene {
+        floor: Some(Floor::new(4.0, BLACK, WHITE)),
+        background: Rc::new(Uniform { color: HdrColor::from_color(Color::new(0, 0, 180)) }),
+        ..make_scene()
+    };
+    let mut framebuffer = Framebuffer::new(20, 10);
+    scene.render(&mut framebuffer);
+    assert_eq!(framebuffer.get_pixels().len(), 200);
+    // top left corner of the image is the top left corner of the screen
+    assert_eq!(framebuffer.get_pixel(0, 0), scene.radiance_at(19, 9));
+    assert_eq!(framebuffer.get_pixel(19, 9), scene.radiance_at(0, 0));
+    let colors = framebuffer.to_colors(scene.tone_mapping, scene.exposure);
+    assert_eq!(colors[20 * 3 + 5], scene.color_at(14, 6));
+}
+
+#[test]
+fn pixel_samples() {
+    let sphere = Sphere { center: Point3::new(-90.0, 10.0, 10.0), radius: 10.0, ..make_sphere() };
+    let scene = Scene {
+        background: Rc::new(Uniform { color: HdrColor::from_color(Color::new(0, 0, 180)) }),
+        spheres: vec![sphere.clone()],
+        ..make_scene()
+    };
+    let floor_sample = scene.sample_at(2, 1);
+    assert_eq!(floor_sample.object_id, raytracer::FLOOR_OBJECT_ID);
+    assert_close_vectors!(floor_sample.normal, Vec3::new(0.0, 0.0, 1.0), 0.001);
+    let floor_point = Point3::new(-1.034482759, 1.034482759, 0.0);
+    assert!(raytracer::are_close(floor_sample.depth, raytracer::get_distance(scene.eye, floor_point)));
+    assert!(!floor_sample.in_shadow);
+
+    let sphere_sample = scene.sample_at(25, 25);
+    assert_eq!(sphere_sample.object_id, raytracer::FLOOR_OBJECT_ID + 1);
+    let sphere_point = Point3::new(-80.266716, 11.622215, 11.622215);
+    assert_close_vectors!(sphere_sample.normal, (sphere_point - sphere.center) * 0.1, 0.001);
+    assert_eq!(sphere_sample.albedo, HdrColor::from_color(sphere.color));
+    assert!(sphere_sample.in_shadow);
+
+    let sky_sample = scene.sample_at(255, 255);
+    assert_eq!(sky_sample.object_id, 0);
+    assert!(sky_sample.depth.is_infinite());
+
+    let mut framebuffer = Framebuffer::new(3, 3);
+    scene.render_with_aovs(&mut framebuffer, &[Aov::ObjectId, Aov::Shadow]);
+    assert_eq!(framebuffer.get_layers().len(), 2);
+    let object_ids = framebuffer.get_layer("object_id").unwrap();
+    assert_eq!(object_ids.get_pixel(1, 2), Aov::ObjectId.get_value(&scene.sample_at(1, 0)));
+}
+
+#[test]
+fn framebuffer_layers() {
+    let mut framebuffer = Framebuffer::new(2, 2);
+    framebuffer.add_layer("depth").set_pixel(1, 0, HdrColor::new(5.0, 5.0, 5.0));
+    assert!(framebuffer.get_layer("normal").is_none());
+    let depth = framebuffer.get_layer("depth").unwrap();
+    assert_eq!(depth.get_pixel(1, 0), HdrColor::new(5.0, 5.0, 5.0));
+    assert_eq!(depth.get_pixel(0, 1), HdrColor::new(0.0, 0.0, 0.0));
+    assert_eq!(framebuffer.get_pixel(1, 0), HdrColor::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn denoise_keeps_edges() {
+    let size = 16;
+    let mut framebuffer = Framebuffer::new(size, size);
+    framebuffer.add_layer("normal");
+    let mut seed: u32 = 1;
+    for y in 0..size {
+        for x in 0..size {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let noise = ((seed >> 16) % 100) as f64 / 500.0 - 0.1;
+            // left half faces one way and is dark, right half faces another way and is bright
+            let (value, normal) = if x < size / 2 { (0.2, HdrColor::new(1.0, 0.0, 0.0)) } else { (0.8, HdrColor::new(0.0, 0.0, 1.0)) };
+            framebuffer.set_pixel(x, y, HdrColor::new(value + noise, value + noise, value + noise));
+            framebuffer.get_layer_mut("normal").unwrap().set_pixel(x, y, normal);
+        }
+    }
+    let denoised = denoise::denoise(&framebuffer, &DenoiseOptions::default());
+    assert!(denoised.get_layer("normal").is_some());
+    let get_error = |fb: &Framebuffer| {
+        let mut error: f64 = 0.0;
+        for y in 0..size {
+            for x in 0..size {
+                let expected = if x < size / 2 { 0.2 } else { 0.8 };
+                error = error.max((fb.get_pixel(x, y).r - expected).abs());
+            }
+        }
+        error
+    };
+    assert!(get_error(&framebuffer) > 0.09);
+    assert!(get_error(&denoised) < 0.05, "{}", get_error(&denoised));
+}
+
+#[test]
+fn save_bmp() {
+    let path = env::temp_dir().join("raytracer_test_save.bmp");
+    let path = path.to_str().unwrap();
+    let mut framebuffer = Framebuffer::new(1, 2);
+    framebuffer.set_pixel(0, 0, HdrColor::new(1.0, 0.0, 0.0));
+    output::save_framebuffer(path, ImageFormat::Bmp, &framebuffer, ToneMapping::Clamp, 0.0).unwrap();
+    let bytes = fs::read(path).unwrap();
+    assert_eq!(&bytes[..2], b"BM");
+    assert_eq!(bytes.len(), 54 + 2 * 4);
+    // bottom row first, blue green red and padding
+    assert_eq!(&bytes[54..], &[0, 0, 0, 0, 0, 0, 255, 0]);
+    fs::remove_file(path).unwrap();
+}
+
 #[test]
 fn ray_from_to() {
-    let a = Point::new(1.0, 2.0, 3.0);
-    let b = Point::new(4.0, 1.0, 8.0);
+    let a = Point3::new(1.0, 2.0, 3.0);
+    let b = Point3::new(4.0, 1.0, 8.0);
     let ray = Ray::from_to(a, b);
     assert_eq!(ray.start, a);
-    assert_close_points!(ray.direction, Point::new(3.0, -1.0, 5.0), 0.0001);
+    assert_close_vectors!(ray.direction, Vec3::new(3.0, -1.0, 5.0), 0.0001);
 }
 
 #[test]
 fn perpendicular_from_point() {
-    let point = Point::new(3.0, 2.0, 0.0);
-    let ray = Ray::from_to(Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 0.0));
+    let point = Point3::new(3.0, 2.0, 0.0);
+    let ray = Ray::from_to(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0));
     let perpendicular_ray = raytracer::get_perpendicular_ray(point, ray).unwrap();
     assert_eq!(perpendicular_ray.start, point);
-    assert_close_points!(perpendicular_ray.direction, Point::new(0.0, -2.0, 0.0), 0.001);
+    assert_close_vectors!(perpendicular_ray.direction, Vec3::new(0.0, -2.0, 0.0), 0.001);
 }
 
 #[test]
 fn sphere_mirroring() {
-    let sphere = Sphere {
-        center: Point::new(0.0, 0.0, 0.0),
-        radius: 1.0,
-        color: BLACK,
-        is_mirror: true,
+    let sphere = Sphere { color: BLACK, is_mirror: true, ..make_sphere() };
+    let point = Point3::new(10.0, 0.0, 0.0);
+    let ray = Ray::from_to(point, Point3::new(1.0, 0.0, 0.0));
+    let refracted_ray = raytracer::get_refraction_from_sphere(ray, &sphere).unwrap();
+    assert_close_points!(refracted_ray.start, Point3::new(1.0, 0.0, 0.0), 0.001);
+    assert_close_vectors!(refracted_ray.direction, Vec3::new(9.0, 0.0, 0.0), 0.001);
+}
+
+#[test]
+fn image_format_from_path() {
+    assert_eq!(ImageFormat::from_path("render.png"), Some(ImageFormat::Png));
+    assert_eq!(ImageFormat::from_path("out/render.BMP"), Some(ImageFormat::Bmp));
+    assert_eq!(ImageFormat::from_path("render"), None);
+    assert_eq!(ImageFormat::from_path("render.jpg"), None);
+}
+
+#[test]
+fn save_png() {
+    let path = env::temp_dir().join("raytracer_test_save.png");
+    let path = path.to_str().unwrap();
+    let pixels = vec![BLACK, WHITE, WHITE, BLACK];
+    output::save_image(path, ImageFormat::Png, 2, 2, &pixels).unwrap();
+    let bytes = fs::read(path).unwrap();
+    assert_eq!(&bytes[..8], b"\x89PNG\r\n\x1a\n");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn rgbe_encoding() {
+    assert_eq!(output::to_rgbe(HdrColor::new(1.0, 0.5, 0.25)), [128, 64, 32, 129]);
+    assert_eq!(output::to_rgbe(HdrColor::new(6.0, 0.0, 0.0)), [192, 0, 0, 131]);
+    assert_eq!(output::to_rgbe(HdrColor::new(0.0, 0.0, 0.0)), [0, 0, 0, 0]);
+}
+
+#[test]
+fn half_float_conversion() {
+    assert_eq!(output::to_half(0.0), 0x0000);
+    assert_eq!(output::to_half(1.0), 0x3c00);
+    assert_eq!(output::to_half(-2.0), 0xc000);
+    assert_eq!(output::to_half(65504.0), 0x7bff);
+    assert_eq!(output::to_half(1e6), 0x7c00);
+    // smallest subnormal
+    assert_eq!(output::to_half(5.960_464_5e-8), 0x0001);
+}
+
+#[test]
+fn save_exr() {
+    let path = env::temp_dir().join("raytracer_test_save.exr");
+    let path = path.to_str().unwrap();
+    let pixels = vec![HdrColor::new(2.0, 0.5, 0.0); 6];
+    output::save_hdr_image(path, ImageFormat::Exr(ExrPrecision::Float), 3, 2, &pixels).unwrap();
+    let bytes = fs::read(path).unwrap();
+    assert_eq!(&bytes[..4], &[0x76, 0x2f, 0x31, 0x01]);
+    // last pixel of the last scanline is in the R channel
+    assert_eq!(&bytes[bytes.len() - 4..], &2.0_f32.to_bits().to_le_bytes());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn save_ppm() {
+    let path = env::temp_dir().join("raytracer_test_save.ppm");
+    let path = path.to_str().unwrap();
+    let pixels = vec![Color::new(1, 2, 3), Color::new(4, 5, 6)];
+    output::save_image(path, ImageFormat::Ppm, 2, 1, &pixels).unwrap();
+    assert_eq!(fs::read(path).unwrap(), b"P6\n2 1\n255\n\x01\x02\x03\x04\x05\x06".to_vec());
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn save_pfm() {
+    let path = env::temp_dir().join("raytracer_test_save.pfm");
+    let path = path.to_str().unwrap();
+    let pixels = vec![HdrColor::new(1.0, 1.0, 1.0), HdrColor::new(2.0, 2.0, 2.0)];
+    output::save_hdr_image(path, ImageFormat::Pfm, 1, 2, &pixels).unwrap();
+    let bytes = fs::read(path).unwrap();
+    let header = b"PF\n1 2\n-1.0\n";
+    assert_eq!(&bytes[..header.len()], header);
+    // bottom scanline goes first
+    assert_eq!(&bytes[header.len()..header.len() + 4], &2.0_f32.to_bits().to_le_bytes());
+    assert_eq!(bytes.len(), header.len() + 2 * 3 * 4);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn matrix_inverse() {
+    let matrix = Mat4::new([
+        [2.0, 0.0, 1.0, 3.0],
+        [0.0, 0.0, 4.0, -1.0],
+        [1.0, 3.0, 0.0, 2.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+    let inverse = matrix.inverse().unwrap();
+    for (row, identity_row) in (matrix * inverse).rows.iter().zip(Mat4::identity().rows.iter()) {
+        for (value, expected) in row.iter().zip(identity_row.iter()) {
+            assert!((value - expected).abs() < 1e-12, "{} {}", value, expected);
+        }
+    }
+    assert_eq!(Mat4::identity().inverse(), Some(Mat4::identity()));
+    // two equal rows
+    let mut singular = matrix;
+    singular.rows[1] = singular.rows[0];
+    assert!(singular.inverse().is_none());
+    let mut broken = matrix;
+    broken.rows[2][1] = f64::NAN;
+    assert!(broken.inverse().is_none());
+    broken.rows[2][1] = f64::INFINITY;
+    assert!(broken.inverse().is_none());
+    assert!(Transform::from_matrix(broken).is_none());
+}
+
+#[test]
+fn refraction() {
+    let normal = Normal3::new(Vec3::new(0.0, 0.0, 1.0));
+    // straight through at any ratio
+    assert_close_vectors!(Vec3::new(0.0, 0.0, -2.0).refract(normal, 1.5).unwrap(), Vec3::new(0.0, 0.0, -1.0), 1e-9);
+    // 45 degrees into a denser medium bends towards the normal by snell's law
+    let half = 0.5_f64.sqrt();
+    let refracted = Vec3::new(1.0, 0.0, -1.0).refract(normal, 1.0 / 1.5).unwrap();
+    assert!((refracted.length() - 1.0).abs() < 1e-9);
+    assert!((refracted.x - half / 1.5).abs() < 1e-9 && refracted.z < 0.0, "{}", refracted);
+    // going out of it at the same angle is past the critical one
+    assert_eq!(Vec3::new(1.0, 0.0, -1.0).refract(normal, 1.5), None);
+    assert_close_vectors!(Vec3::new(1.0, 0.0, -1.0).reflect(normal), Vec3::new(1.0, 0.0, 1.0), 1e-9);
+}
+
+#[test]
+fn normals() {
+    let normal = Normal3::new(Vec3::new(0.0, 3.0, 4.0));
+    assert_close_vectors!(normal.to_vec(), Vec3::new(0.0, 0.6, 0.8), 1e-9);
+    assert_eq!((-normal).to_vec(), Vec3::new(0.0, -0.6, -0.8));
+    assert_eq!(Normal3::try_new(Vec3::new(0.0, 3.0, 4.0)), Some(normal));
+    assert_eq!(Normal3::try_new(Vec3::new(0.0, 0.0, 0.0)), None);
+    assert_eq!(Normal3::try_new(Vec3::new(0.0, f64::NAN, 1.0)), None);
+    assert_eq!(Normal3::try_new(Vec3::new(f64::INFINITY, 0.0, 0.0)), None);
+}
+
+#[test]
+fn transforms() {
+    let point = Point3::new(1.0, 2.0, 3.0);
+    let quarter_turn = Transform::rotation(Vec3::new(0.0, 0.0, 2.0), f64::consts::PI / 2.0).unwrap();
+    assert_close_points!(quarter_turn.apply_to_point(point), Point3::new(-2.0, 1.0, 3.0), 1e-9);
+    let transform = Transform::scaling(2.0, 1.0, 1.0).unwrap()
+        .then(&quarter_turn)
+        .then(&Transform::translation(Vec3::new(10.0, 0.0, 0.0)));
+    assert_close_points!(transform.apply_to_point(point), Point3::new(8.0, 2.0, 3.0), 1e-9);
+    assert_close_vectors!(transform.apply_to_vector(point.to_vec()), Vec3::new(-2.0, 2.0, 3.0), 1e-9);
+    assert_close_points!(transform.inverse().apply_to_point(Point3::new(8.0, 2.0, 3.0)), point, 1e-9);
+    let from_matrix = Transform::from_matrix(transform.get_matrix()).unwrap();
+    assert_close_points!(from_matrix.inverse().apply_to_point(Point3::new(8.0, 2.0, 3.0)), point, 1e-9);
+    let mut flat = Transform::identity().get_matrix();
+    flat.rows[2][2] = 0.0;
+    assert!(Transform::from_matrix(flat).is_none());
+    assert!(Transform::scaling(1.0, 0.0, 1.0).is_none());
+    assert!(Transform::scaling(1.0, 1.0, f64::NAN).is_none());
+    assert!(Transform::rotation(Vec3::zero(), 1.0).is_none());
+    assert!(Transform::rotation(Vec3::new(0.0, 0.0, 1.0), f64::INFINITY).is_none());
+    // normals of a squashed surface tilt away from the squashed axis
+    let squash = Transform::scaling(1.0, 1.0, 0.5).unwrap();
+    let half = 0.5_f64.sqrt();
+    let normal = squash.apply_to_normal(Normal3::new(Vec3::new(half, 0.0, half))).unwrap();
+    assert_close_vectors!(normal.to_vec(), Vec3::new(1.0, 0.0, 2.0) / 5.0_f64.sqrt(), 1e-9);
+}
+
+#[test]
+fn ellipsoid_instances() {
+    let sphere: Rc<dyn Shape> = Rc::new(make_sphere());
+    let stretched = Transform::scaling(1.0, 3.0, 1.0).unwrap().then(&Transform::translation(Vec3::new(-10.0, 0.0, 0.0)));
+    let first = Instance::new(sphere.clone(), stretched);
+    let second = Instance::new(sphere.clone(), Transform::translation(Vec3::new(-10.0, 20.0, 0.0)));
+    assert_eq!(Rc::strong_count(&sphere), 3);
+    let ray = Ray::new(Point3::new(0.0, 2.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+    let points = first.get_colored_intersections(ray);
+    assert_eq!(points.len(), 2);
+    let closest = raytracer::get_closest_point(ray.start, &points).unwrap();
+    let x = 10.0 - (1.0 - 4.0 / 9.0_f64).sqrt();
+    assert_close_points!(closest.point, Point3::new(-x, 2.0, 0.0), 1e-9);
+    assert!(closest.normal.x > 0.0 && closest.normal.y > 0.0);
+    assert!(raytracer::are_close(closest.normal.length(), 1.0));
+    assert!(second.get_colored_intersections(ray).is_empty());
+}
+
+#[test]
+fn objects_in_scene() {
+    let sphere = Sphere { radius: 10.0, ..make_sphere() };
+    let mirror = Sphere { center: Point3::new(-200.0, 30.0, 30.0), radius: 20.0, is_mirror: true, ..sphere.clone() };
+    let in_front_of_mirror = Instance::new(Rc::new(sphere), Transform::translation(Vec3::new(-100.0, 30.0, 30.0)));
+    let scene = Scene {
+        background: Rc::new(Uniform { color: HdrColor::from_color(Color::new(0, 0, 180)) }),
+        spheres: vec![mirror],
+        objects: vec![Rc::new(in_front_of_mirror)],
+        ..make_scene()
+    };
+    let sample = scene.sample_at(30, 30);
+    assert_eq!(sample.object_id, raytracer::FLOOR_OBJECT_ID + 2);
+    assert!(raytracer::are_close(sample.depth, 120.0));
+}
+
+#[test]
+fn cubic_and_quartic_equations() {
+    let mut roots = raytracer::get_cubic_equation_roots(2.0, -12.0, 22.0, -12.0);
+    roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(roots.len(), 3);
+    for (root, expected) in roots.iter().zip(&[1.0, 2.0, 3.0]) {
+        assert!((root - expected).abs() < 1e-9, "wrong root {}", root);
+    }
+    assert_eq!(raytracer::get_cubic_equation_roots(1.0, 0.0, 1.0, 0.0), vec![0.0]);
+    // (x^2 - 1) * (x^2 - 16) and (x - 1) * (x - 2) * (x - 3) * (x - 5)
+    for &(coefficients, expected) in &[([1.0, 0.0, -17.0, 0.0, 16.0], [-4.0, -1.0, 1.0, 4.0]), ([1.0, -11.0, 41.0, -61.0, 30.0], [1.0, 2.0, 3.0, 5.0])] {
+        let [a, b, c, d, e] = coefficients;
+        let mut roots = raytracer::get_quartic_equation_roots(a, b, c, d, e);
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip(&expected) {
+            assert!((root - expected).abs() < 1e-9, "wrong root {}", root);
+        }
+    }
+    assert!(raytracer::get_quartic_equation_roots(1.0, 0.0, 0.0, 0.0, 1.0).is_empty());
+}
+
+#[test]
+fn boxes_and_disks() {
+    let color = Color::new(200, 0, 0);
+    let cuboid = Cuboid::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 4.0, 6.0), color);
+    let ray = Ray::new(Point3::new(0.0, 10.0, 1.0), Vec3::new(0.0, -1.0, 0.0));
+    let closest = raytracer::get_closest_point(ray.start, &cuboid.get_colored_intersections(ray)).unwrap();
+    assert_close_points!(closest.point, Point3::new(0.0, 2.0, 1.0), 1e-9);
+    assert_close_vectors!(closest.normal.to_vec(), Vec3::new(0.0, 1.0, 0.0), 1e-9);
+    assert_eq!(closest.color, color);
+    assert!(cuboid.get_colored_intersections(Ray::new(Point3::new(0.0, 10.0, 4.0), Vec3::new(0.0, -1.0, 0.0))).is_empty());
+    // a ray starting inside only leaves it
+    let inside = cuboid.get_colored_intersections(Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)));
+    assert_eq!(inside.len(), 1);
+    assert_close_vectors!(inside[0].normal.to_vec(), Vec3::new(0.0, 0.0, -1.0), 1e-9);
+    // the corner of a box turned around the vertical axis faces the ray
+    let turned = Instance::new(Rc::new(cuboid), Transform::rotation(Vec3::new(0.0, 0.0, 1.0), f64::consts::PI / 4.0).unwrap());
+    let ray = Ray::new(Point3::new(10.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+    let closest = raytracer::get_closest_point(ray.start, &turned.get_colored_intersections(ray)).unwrap();
+    assert!(closest.point.x > 1.0 && closest.point.x < 2.0);
+    let disk = Disk { center: Point3::new(0.0, 0.0, 5.0), normal: Normal3::new(Vec3::new(0.0, 0.0, 1.0)), radius: 2.0, color };
+    let points = disk.get_colored_intersections(Ray::new(Point3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)));
+    assert_eq!(points.len(), 1);
+    assert_close_points!(points[0].point, Point3::new(1.0, 1.0, 5.0), 1e-9);
+    assert!(disk.get_colored_intersections(Ray::new(Point3::new(2.0, 2.0, 0.0), Vec3::new(0.0, 0.0, 1.0))).is_empty());
+}
+
+#[test]
+fn cylinders_and_cones() {
+    let color = Color::new(0, 200, 0);
+    let cylinder = Cylinder { base: Point3::new(0.0, 0.0, 0.0), radius: 2.0, height: 10.0, color };
+    let ray = Ray::new(Point3::new(10.0, 0.0, 5.0), Vec3::new(-1.0, 0.0, 0.0));
+    let points = cylinder.get_colored_intersections(ray);
+    assert_eq!(points.len(), 2);
+    let closest = raytracer::get_closest_point(ray.start, &points).unwrap();
+    assert_close_points!(closest.point, Point3::new(2.0, 0.0, 5.0), 1e-9);
+    assert_close_vectors!(closest.normal.to_vec(), Vec3::new(1.0, 0.0, 0.0), 1e-9);
+    assert!(cylinder.get_colored_intersections(Ray::new(Point3::new(10.0, 0.0, 11.0), Vec3::new(-1.0, 0.0, 0.0))).is_empty());
+    let ray = Ray::new(Point3::new(1.0, 0.0, 20.0), Vec3::new(0.0, 0.0, -1.0));
+    let closest = raytracer::get_closest_point(ray.start, &cylinder.get_colored_intersections(ray)).unwrap();
+    assert_close_points!(closest.point, Point3::new(1.0, 0.0, 10.0), 1e-9);
+    assert_close_vectors!(closest.normal.to_vec(), Vec3::new(0.0, 0.0, 1.0), 1e-9);
+
+    let cone = Cone { base: Point3::new(0.0, 0.0, 0.0), radius: 2.0, height: 2.0, color };
+    let ray = Ray::new(Point3::new(10.0, 0.0, 1.0), Vec3::new(-1.0, 0.0, 0.0));
+    let closest = raytracer::get_closest_point(ray.start, &cone.get_colored_intersections(ray)).unwrap();
+    assert_close_points!(closest.point, Point3::new(1.0, 0.0, 1.0), 1e-9);
+    let half = 0.5_f64.sqrt();
+    assert_close_vectors!(closest.normal.to_vec(), Vec3::new(half, 0.0, half), 1e-9);
+    // the cone is closed from below
+    let ray = Ray::new(Point3::new(0.5, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+    let closest = raytracer::get_closest_point(ray.start, &cone.get_colored_intersections(ray)).unwrap();
+    assert_close_points!(closest.point, Point3::new(0.5, 0.0, 0.0), 1e-9);
+    assert_close_vectors!(closest.normal.to_vec(), Vec3::new(0.0, 0.0, -1.0), 1e-9);
+    assert!(cone.get_colored_intersections(Ray::new(Point3::new(10.0, 0.0, 2.5), Vec3::new(-1.0, 0.0, 0.0))).is_empty());
+}
+
+#[test]
+fn tori() {
+    let torus = Torus { center: Point3::new(0.0, 0.0, 0.0), major_radius: 10.0, minor_radius: 2.0, color: Color::new(0, 0, 200) };
+    let ray = Ray::new(Point3::new(20.0, 0.0, 0.0), Vec3::new(-2.0, 0.0, 0.0));
+    let points = torus.get_colored_intersections(ray);
+    assert_eq!(points.len(), 4);
+    let closest = raytracer::get_closest_point(ray.start, &points).unwrap();
+    assert_close_points!(closest.point, Point3::new(12.0, 0.0, 0.0), 1e-9);
+    assert_close_vectors!(closest.normal.to_vec(), Vec3::new(1.0, 0.0, 0.0), 1e-9);
+    // the hole in the middle
+    assert!(torus.get_colored_intersections(Ray::new(Point3::new(0.0, 0.0, 20.0), Vec3::new(0.0, 0.0, -1.0))).is_empty());
+    let ray = Ray::new(Point3::new(0.0, 10.0, 20.0), Vec3::new(0.0, 0.0, -1.0));
+    let closest = raytracer::get_closest_point(ray.start, &torus.get_colored_intersections(ray)).unwrap();
+    assert_close_points!(closest.point, Point3::new(0.0, 10.0, 2.0), 1e-9);
+    assert_close_vectors!(closest.normal.to_vec(), Vec3::new(0.0, 0.0, 1.0), 1e-9);
+    // slanted rays hit the surface too
+    let ray = Ray::new(Point3::new(30.0, 5.0, 3.0), Vec3::new(-3.0, -0.3, -0.3));
+    let points = torus.get_colored_intersections(ray);
+    assert!(points.len() >= 2);
+    for point in points {
+        let ring = (point.point.x.powi(2) + point.point.y.powi(2)).sqrt() - 10.0;
+        assert!((ring.powi(2) + point.point.z.powi(2) - 4.0).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn constructive_solid_geometry() {
+    let solid_sphere = Sphere { radius: 10.0, ..make_sphere() };
+    let sphere: Rc<dyn Shape> = Rc::new(solid_sphere.clone());
+    let carved_color = Color::new(150, 0, 0);
+    let cuboid: Rc<dyn Shape> = Rc::new(Cuboid::new(Point3::new(10.0, 0.0, 0.0), Vec3::new(10.0, 10.0, 10.0), carved_color));
+    let ray = Ray::new(Point3::new(20.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+
+    let difference = Csg::new(CsgOperation::Difference, sphere.clone(), cuboid.clone());
+    let points = difference.get_colored_intersections(ray);
+    assert_eq!(points.len(), 2);
+    let closest = raytracer::get_closest_point(ray.start, &points).unwrap();
+    assert_close_points!(closest.point, Point3::new(5.0, 0.0, 0.0), 1e-9);
+    assert_close_vectors!(closest.normal.to_vec(), Vec3::new(1.0, 0.0, 0.0), 1e-9);
+    assert_eq!(closest.color, carved_color);
+    // the ray starts inside the box, so only the far side of the sphere is left
+    let inside = Ray::new(Point3::new(8.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+    let points = difference.get_colored_intersections(inside);
+    assert_eq!(points.len(), 2);
+
+    let intersection = Csg::new(CsgOperation::Intersection, sphere.clone(), cuboid.clone());
+    let points = intersection.get_colored_intersections(ray);
+    assert_eq!(points.len(), 2);
+    let closest = raytracer::get_closest_point(ray.start, &points).unwrap();
+    assert_close_points!(closest.point, Point3::new(10.0, 0.0, 0.0), 1e-9);
+    assert_eq!(closest.color, Color::new(0, 150, 0));
+
+    let union = Csg::new(CsgOperation::Union, sphere.clone(), cuboid.clone());
+    let points = union.get_colored_intersections(ray);
+    assert_eq!(points.len(), 2);
+    let closest = raytracer::get_closest_point(ray.start, &points).unwrap();
+    assert_close_points!(closest.point, Point3::new(15.0, 0.0, 0.0), 1e-9);
+    assert_eq!(closest.color, carved_color);
+
+    // operations nest
+    let hollow = Csg::new(CsgOperation::Difference, Rc::new(union), Rc::new(Sphere { radius: 5.0, ..solid_sphere }));
+    assert_eq!(hollow.get_colored_intersections(ray).len(), 4);
+}
+
+#[test]
+fn placed_and_finite_floors() {
+    // a wall facing +x with u going along y and v going up
+    let mut wall = Floor::with_texture(Rc::new(Stripes { width: 2.0, first_color: BLACK, second_color: WHITE }));
+    wall.set_placement(Point3::new(-50.0, 0.0, 10.0), Normal3::new(Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+    assert_eq!(wall.color_at(Point3::new(-50.0, 1.0, 0.0)), BLACK);
+    assert_eq!(wall.color_at(Point3::new(-50.0, 3.0, 0.0)), WHITE);
+    // points off the plane don't panic
+    assert_eq!(wall.color_at(Point3::new(7.0, 3.0, 0.0)), WHITE);
+    let ray = Ray::new(Point3::new(0.0, 1.0, 100.0), Vec3::new(-1.0, 0.0, 0.0));
+    let points = wall.get_colored_intersections(ray);
+    assert_eq!(points.len(), 1);
+    assert_close_points!(points[0].point, Point3::new(-50.0, 1.0, 100.0), 1e-9);
+    assert_close_vectors!(points[0].normal.to_vec(), Vec3::new(1.0, 0.0, 0.0), 1e-9);
+    wall.set_size(Some((20.0, 40.0)));
+    assert!(wall.get_colored_intersections(ray).is_empty());
+    assert_eq!(wall.get_colored_intersections(Ray::new(Point3::new(0.0, 9.0, 29.0), Vec3::new(-1.0, 0.0, 0.0))).len(), 1);
+    assert!(wall.get_colored_intersections(Ray::new(Point3::new(0.0, 11.0, 29.0), Vec3::new(-1.0, 0.0, 0.0))).is_empty());
+
+    let sky = HdrColor::from_color(Color::new(0, 0, 180));
+    let mut scene = Scene { floor: None, background: Rc::new(Uniform { color: sky }), ..make_scene() };
+    let sample = scene.sample_at(2, 1);
+    assert_eq!(sample.object_id, 0);
+    assert_eq!(sample.radiance, sky);
+    scene.objects.push(Rc::new(wall));
+    let sample = scene.sample_at(20, 20);
+    assert_eq!(sample.object_id, raytracer::FLOOR_OBJECT_ID + 1);
+    assert_close_vectors!(sample.normal, Vec3::new(1.0, 0.0, 0.0), 1e-9);
+}
+
+#[test]
+fn distance_fields() {
+    let ball: Rc<dyn DistanceField> = Rc::new(Ball { center: Point3::new(0.0, 0.0, 0.0), radius: 2.0 });
+    assert!(raytracer::are_close(ball.distance_at(Point3::new(5.0, 0.0, 0.0)), 3.0));
+    assert!(raytracer::are_close(ball.distance_at(Point3::new(0.0, 0.0, 0.0)), -2.0));
+    let block: Rc<dyn DistanceField> = Rc::new(Block { center: Point3::new(4.0, 0.0, 0.0), size: Vec3::new(2.0, 2.0, 2.0), rounding: 0.0 });
+    assert!(raytracer::are_close(block.distance_at(Point3::new(4.0, 0.0, 3.0)), 2.0));
+    assert!(raytracer::are_close(block.distance_at(Point3::new(8.0, 5.0, 0.0)), 5.0));
+    // blending fills the gap between the ball and the block
+    let point = Point3::new(2.5, 0.0, 0.0);
+    let union = sdf::Union { first: ball.clone(), second: block.clone() };
+    let smooth = SmoothUnion { first: ball.clone(), second: block.clone(), smoothness: 1.0 };
+    assert!(raytracer::are_close(union.distance_at(point), 0.5));
+    assert!(smooth.distance_at(point) < 0.5);
+    assert!(raytracer::are_close(smooth.distance_at(Point3::new(-5.0, 0.0, 0.0)), 3.0));
+    let repetition = Repetition { field: ball.clone(), period: Vec3::new(10.0, 0.0, 0.0) };
+    assert!(raytracer::are_close(repetition.distance_at(Point3::new(23.0, 0.0, 0.0)), 1.0));
+    assert!(raytracer::are_close(repetition.distance_at(Point3::new(0.0, 13.0, 0.0)), 11.0));
+    // a quarter turn at the height of 1.0
+    let bar: Rc<dyn DistanceField> = Rc::new(Block { center: Point3::new(0.0, 0.0, 0.0), size: Vec3::new(10.0, 1.0, 10.0), rounding: 0.0 });
+    let twist = Twist { field: bar, rate: f64::consts::PI / 2.0 };
+    assert!(twist.distance_at(Point3::new(4.0, 0.0, 0.0)) < 0.0);
+    assert!(twist.distance_at(Point3::new(4.0, 0.0, 1.0)) > 0.0);
+    assert!(twist.distance_at(Point3::new(0.0, 4.0, 1.0)) < 0.0);
+}
+
+#[test]
+fn sdf_objects() {
+    let field = Rc::new(SmoothUnion {
+        first: Rc::new(Ball { center: Point3::new(-100.0, 0.0, 10.0), radius: 10.0 }),
+        second: Rc::new(Ball { center: Point3::new(-100.0, 30.0, 10.0), radius: 10.0 }),
+        smoothness: 5.0,
+    });
+    let object = SdfObject::new(field, Color::new(0, 150, 0));
+    let ray = Ray::new(Point3::new(0.0, 0.0, 10.0), Vec3::new(-3.0, 0.0, 0.0));
+    let points = object.get_colored_intersections(ray);
+    assert_eq!(points.len(), 1);
+    assert_close_points!(points[0].point, Point3::new(-90.0, 0.0, 10.0), 1e-3);
+    assert_close_vectors!(points[0].normal.to_vec(), Vec3::new(1.0, 0.0, 0.0), 1e-3);
+    assert!(object.get_colored_intersections(Ray::new(Point3::new(0.0, 0.0, 30.0), Vec3::new(-1.0, 0.0, 0.0))).is_empty());
+    // rays leaving the surface don't hit it again
+    assert!(object.get_colored_intersections(Ray::new(points[0].point, Vec3::new(1.0, 0.0, 1.0))).is_empty());
+    let bulb = SdfObject::new(Rc::new(Mandelbulb { power: 8.0, iterations: 10 }), Color::new(150, 0, 0));
+    let points = bulb.get_colored_intersections(Ray::new(Point3::new(3.0, 0.1, 0.2), Vec3::new(-1.0, 0.0, 0.0)));
+    assert_eq!(points.len(), 1);
+    assert!(points[0].point.x > 0.5 && points[0].point.x < 1.3);
+
+    // shades and casts shadows like a sphere
+    let scene = Scene {
+        light_source: Point3::new(-100.0, 0.0, 200.0),
+        background: Rc::new(Uniform { color: HdrColor::from_color(Color::new(0, 0, 180)) }),
+        objects: vec![Rc::new(object)],
+        ..make_scene()
+    };
+    let sample = scene.sample_at(30, 27);
+    assert_eq!(sample.object_id, raytracer::FLOOR_OBJECT_ID + 1);
+    assert!(sample.normal.x > 0.0 && sample.normal.z > 0.0);
+    assert!(!sample.in_shadow);
+    let shadow_sample = scene.sample_at(21, 23);
+    assert_eq!(shadow_sample.object_id, raytracer::FLOOR_OBJECT_ID);
+    assert!(shadow_sample.in_shadow);
+}
+
+#[test]
+fn blobs() {
+    let sphere = Sphere { radius: 10.0, color: Color::new(200, 0, 0), ..make_sphere() };
+    // a lone component looks like its sphere
+    let lone = Blob::from_spheres(std::slice::from_ref(&sphere), 0.25);
+    let ray = Ray::new(Point3::new(50.0, 0.0, 0.0), Vec3::new(-2.0, 0.0, 0.0));
+    let points = lone.get_colored_intersections(ray);
+    assert_eq!(points.len(), 2);
+    let closest = raytracer::get_closest_point(ray.start, &points).unwrap();
+    assert_close_points!(closest.point, Point3::new(10.0, 0.0, 0.0), 1e-6);
+    assert_close_vectors!(closest.normal.to_vec(), Vec3::new(1.0, 0.0, 0.0), 1e-9);
+    assert_eq!(closest.color, sphere.color);
+    assert!(raytracer::are_close(lone.get_field(closest.point), 0.25));
+
+    // two close spheres melt into one surface that bridges the gap between them
+    let green = Sphere { center: Point3::new(0.0, 22.0, 0.0), color: Color::new(0, 200, 0), ..sphere.clone() };
+    let pair = Blob::from_spheres(&[sphere.clone(), green], 0.25);
+    let between = Ray::new(Point3::new(50.0, 11.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+    assert!(sphere.get_intersections(between).is_empty());
+    let points = pair.get_colored_intersections(between);
+    assert_eq!(points.len(), 2);
+    let closest = raytracer::get_closest_point(between.start, &points).unwrap();
+    assert_eq!(closest.color, Color::new(100, 100, 0));
+    assert_close_vectors!(closest.normal.to_vec(), Vec3::new(1.0, 0.0, 0.0), 1e-6);
+
+    // negative strength carves a dent
+    let mut dented = lone.clone();
+    dented.components.push(BlobComponent { center: Point3::new(12.0, 0.0, 0.0), radius: 8.0, strength: -1.0, color: BLACK });
+    let closest = raytracer::get_closest_point(ray.start, &dented.get_colored_intersections(ray)).unwrap();
+    assert!(closest.point.x < 9.0);
+    assert!(lone.get_colored_intersections(Ray::new(Point3::new(50.0, 30.0, 0.0), Vec3::new(-1.0, 0.0, 0.0))).is_empty());
+}
+
+struct BrokenField;
+
+impl DistanceField for BrokenField {
+    // a wall at x = -10, but only along the x axis
+    fn distance_at(&self, point: Point3) -> f64 {
+        if point.y == 0.0 && point.z == 0.0 { point.x + 10.0 } else { f64::NAN }
+    }
+}
+
+#[test]
+fn degenerate_normals() {
+    let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    assert!(Normal3::try_new(Vec3::zero()).is_none());
+    assert!(Normal3::try_new(Vec3::new(f64::NAN, 0.0, 1.0)).is_none());
+    assert!(Normal3::try_new(Vec3::new(f64::INFINITY, 0.0, 1.0)).is_none());
+    // a sphere shrunk to a point, also by an animation, can't be hit
+    let point = Sphere { radius: 0.0, is_mirror: true, ..make_sphere() };
+    assert!(point.get_colored_intersections(ray).is_empty());
+    assert!(raytracer::get_refraction_from_sphere(ray, &point).is_none());
+    let mut scene = Scene { spheres: vec![make_sphere()], ..make_scene() };
+    let animation = SceneAnimation {
+        spheres: vec![SphereAnimation { index: 0, radius: Track::new(vec![Keyframe::linear(0.0, 1.0), Keyframe::linear(1.0, 0.0)]), ..Default::default() }],
+        ..Default::default()
+    };
+    animation.apply(&mut scene, 1.0).unwrap();
+    assert!(scene.spheres[0].get_colored_intersections(ray).is_empty());
+    // a flat cone is only its base
+    let flat = Cone { base: Point3::new(0.0, 0.0, 0.0), radius: 2.0, height: 0.0, color: Color::new(0, 150, 0) };
+    for direction in [Vec3::new(0.0, 0.0, -1.0), Vec3::new(-1.0, 0.0, -1.0)].iter() {
+        let points = flat.get_colored_intersections(Ray::new(Point3::new(1.0, 0.0, 1.0), *direction));
+        assert!(points.iter().all(|point| point.normal.to_vec() == Vec3::new(0.0, 0.0, -1.0)));
+    }
+    // fields without a gradient and overflowing blobs face the ray
+    let object = SdfObject::new(Rc::new(BrokenField), Color::new(0, 150, 0));
+    let points = object.get_colored_intersections(Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)));
+    assert_eq!(points.len(), 1);
+    assert_eq!(points[0].normal.to_vec(), Vec3::new(1.0, 0.0, 0.0));
+    let component = BlobComponent { center: Point3::new(0.0, 0.0, 0.0), radius: 10.0, strength: 1.0, color: Color::new(0, 150, 0) };
+    let blob = Blob {
+        components: vec![component, BlobComponent { center: Point3::new(0.0, 5.0, 0.0), strength: 1e300, ..component }],
+        threshold: 0.25,
+    };
+    let points = blob.get_colored_intersections(Ray::new(Point3::new(50.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)));
+    assert!(!points.is_empty());
+    assert!(points.iter().all(|point| point.normal.to_vec() == Vec3::new(1.0, 0.0, 0.0)));
+    // a transform squashing the space too much to invert has no normals
+    let squash = Transform::scaling(1.0, 1.0, 1e-200).unwrap();
+    assert!(squash.then(&squash).apply_to_normal(Normal3::new(Vec3::new(0.0, 0.0, 1.0))).is_none());
+}
+
+#[test]
+fn textured_shapes() {
+    let stripes: Rc<dyn Texture> = Rc::new(Stripes { width: 2.0, first_color: BLACK, second_color: WHITE });
+    let cuboid: Rc<dyn Shape> = Rc::new(Cuboid::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(10.0, 10.0, 10.0), Color::new(0, 150, 0)));
+    let textured = Textured::new(cuboid, stripes.clone());
+    let color_from = |shape: &dyn Shape, start: Point3| {
+        let ray = Ray::new(start, Vec3::new(0.0, 0.0, -1.0));
+        raytracer::get_closest_point(ray.start, &shape.get_colored_intersections(ray)).unwrap().color
     };
-    let point = Point::new(10.0, 0.0, 0.0);
-    let ray = Ray::from_to(point, Point::new(1.0, 0.0, 0.0));
-    let refracted_ray = raytracer::get_refraction_from_sphere(ray, sphere).unwrap();
-    assert_close_points!(refracted_ray.start, Point::new(1.0, 0.0, 0.0), 0.001);
-    assert_close_points!(refracted_ray.direction, Point::new(9.0, 0.0, 0.0), 0.001);
+    assert_eq!(color_from(&textured, Point3::new(1.0, 0.0, 20.0)), BLACK);
+    assert_eq!(color_from(&textured, Point3::new(3.0, 0.0, 20.0)), WHITE);
+    // the texture moves along with an instance of the textured shape
+    let moved = Instance::new(Rc::new(textured), Transform::translation(Vec3::new(2.0, 0.0, 0.0)));
+    assert_eq!(color_from(&moved, Point3::new(3.0, 0.0, 20.0)), BLACK);
+    // distance fields and blobs take textures the same way
+    let ball: Rc<dyn Shape> = Rc::new(SdfObject::new(Rc::new(Ball { center: Point3::new(0.0, 0.0, 0.0), radius: 5.0 }), BLACK));
+    let gradient = Rc::new(Gradient {
+        start: Point3::new(0.0, 0.0, 0.0),
+        end: Point3::new(0.0, 0.0, 10.0),
+        first_color: BLACK,
+        second_color: WHITE,
+    });
+    let textured_ball = Textured::new(ball, gradient);
+    assert_eq!(color_from(&textured_ball, Point3::new(0.0, 0.0, 20.0)), Color::new(125, 125, 125));
+    let blob: Rc<dyn Shape> = Rc::new(Blob::from_spheres(&[Sphere { radius: 5.0, ..make_sphere() }], 0.25));
+    assert_eq!(color_from(&Textured::new(blob, stripes), Point3::new(3.0, 0.0, 20.0)), WHITE);
+}
+
+#[test]
+fn seeded_random_numbers() {
+    let numbers: Vec<u64> = (0..4).map({ let mut rng = Rng::new(7); move |_| rng.next_u64() }).collect();
+    let again: Vec<u64> = (0..4).map({ let mut rng = Rng::new(7); move |_| rng.next_u64() }).collect();
+    assert_eq!(numbers, again);
+    assert_ne!(Rng::with_stream(7, 1).next_u64(), Rng::with_stream(7, 2).next_u64());
+    let mut rng = Rng::new(0);
+    let mean = (0..10000).map(|_| rng.next_f64()).inspect(|x| assert!(*x >= 0.0 && *x < 1.0)).sum::<f64>() / 10000.0;
+    assert!((mean - 0.5).abs() < 0.02);
+}
+
+#[test]
+fn thin_lens() {
+    let mut rng = Rng::new(1);
+    let mut camera = Camera::thin_lens(2.0, 100.0);
+    for _ in 0..1000 {
+        let (x, y) = camera.sample_aperture(&mut rng);
+        assert!(x * x + y * y <= 4.0);
+    }
+    // the corners of a square aperture point along the axes
+    camera.blades = Some(4);
+    let mut reached_corner = false;
+    for _ in 0..1000 {
+        let (x, y) = camera.sample_aperture(&mut rng);
+        assert!(x.abs() + y.abs() <= 2.0 + 1e-9);
+        reached_corner |= x.abs() > 1.6;
+    }
+    assert!(reached_corner);
+    // every lens ray meets the pinhole ray at the focus distance
+    let pinhole = Ray::new(Point3::new(50.0, 0.0, 0.0), Vec3::new(-1.0, 0.5, 0.25));
+    let focus_point = Point3::new(-50.0, 50.0, 25.0);
+    for _ in 0..10 {
+        let ray = camera.get_lens_ray(pinhole, &mut rng);
+        assert!(raytracer::are_close(ray.start.x, 50.0));
+        let k = (focus_point.x - ray.start.x) / ray.direction.x;
+        assert_close_points!(ray.start + ray.direction * k, focus_point, 1e-9);
+    }
+    assert_eq!(Camera::pinhole().get_lens_ray(pinhole, &mut rng).start, pinhole.start);
+}
+
+#[test]
+fn depth_of_field() {
+    let sphere = Sphere { center: Point3::new(-100.0, 30.0, 30.0), radius: 20.0, ..make_sphere() };
+    let mut scene = Scene {
+        floor: None,
+        light_source: Point3::new(0.0, 30.0, 200.0),
+        spheres: vec![sphere],
+        ..make_scene()
+    };
+    // just outside the silhouette of the sphere
+    let (y, z) = (30, 38);
+    assert_eq!(scene.sample_at(y, z).object_id, 0);
+    let sharp = scene.sample_at(30, 30).radiance;
+    scene.camera = Camera { samples: 64, ..Camera::thin_lens(10.0, 110.0) };
+    // in focus the middle of the sphere looks the same and the sky around stays dark
+    assert_close_hdr_colors!(scene.sample_at(30, 30).radiance, sharp, 1e-3);
+    assert_eq!(scene.sample_at(y, z).radiance, HdrColor::new(0.0, 0.0, 0.0));
+    // out of focus the sphere bleeds into the sky
+    scene.camera.focus_distance = 20.0;
+    assert!(scene.sample_at(y, z).radiance.g > 0.0);
+    assert_eq!(scene.sample_at(y, z), scene.sample_at(y, z));
+}
+
+#[test]
+fn projections() {
+    let eye = Point3::new(100.0, 50.0, 50.0);
+    let mut camera = Camera::pinhole();
+    let perspective = camera.get_pinhole_ray(eye, 60.0, 50.0).unwrap();
+    assert_close_vectors!(perspective.direction, Vec3::new(-100.0, 10.0, 0.0), 1e-9);
+
+    camera.projection = Projection::Orthographic { scale: 2.0 };
+    let ray = camera.get_pinhole_ray(eye, 60.0, 45.0).unwrap();
+    assert_close_points!(ray.start, Point3::new(100.0, 70.0, 40.0), 1e-9);
+    assert_close_vectors!(ray.direction, Vec3::new(-1.0, 0.0, 0.0), 1e-9);
+
+    camera.projection = Projection::Fisheye { field_of_view: f64::consts::PI };
+    let center = camera.get_pinhole_ray(eye, 50.0, 50.0).unwrap();
+    assert_close_vectors!(center.direction, Vec3::new(-1.0, 0.0, 0.0), 1e-9);
+    // the edge of the circle looks sideways, half way there is 45 degrees
+    let edge = camera.get_pinhole_ray(eye, 50.0, 150.0).unwrap();
+    assert_close_vectors!(edge.direction, Vec3::new(0.0, 0.0, 1.0), 1e-9);
+    let half = 0.5_f64.sqrt();
+    assert_close_vectors!(camera.get_pinhole_ray(eye, 0.0, 50.0).unwrap().direction, Vec3::new(-half, -half, 0.0), 1e-9);
+    assert!(camera.get_pinhole_ray(eye, 150.0, 150.0).is_none());
+
+    camera.projection = Projection::Equirectangular;
+    assert_close_vectors!(camera.get_pinhole_ray(eye, 50.0, 50.0).unwrap().direction, Vec3::new(-1.0, 0.0, 0.0), 1e-9);
+    // the left and right edges meet behind the eye, the pixels are square
+    assert_close_vectors!(camera.get_pinhole_ray(eye, 250.0, 50.0).unwrap().direction, Vec3::new(1.0, 0.0, 0.0), 1e-9);
+    assert_close_vectors!(camera.get_pinhole_ray(eye, 150.0, 50.0).unwrap().direction, Vec3::new(0.0, 1.0, 0.0), 1e-9);
+    assert_close_vectors!(camera.get_pinhole_ray(eye, 50.0, 150.0).unwrap().direction, Vec3::new(0.0, 0.0, 1.0), 1e-9);
+    assert!(camera.get_pinhole_ray(eye, 50.0, 151.0).is_none());
+    assert!(camera.get_pinhole_ray(eye, 251.0, 50.0).is_none());
+    assert_eq!(Projection::Equirectangular.get_image_width(100), 200);
+    assert_eq!(Projection::Perspective.get_image_width(100), 100);
+    assert_eq!(Projection::from_name("fisheye"), Some(Projection::Fisheye { field_of_view: f64::consts::PI }));
+    assert_eq!(Projection::from_name("pinhole"), None);
+}
+
+#[test]
+fn panoramic_scene() {
+    let sphere = Sphere { center: Point3::new(60.0, 60.0, 30.0), radius: 10.0, ..make_sphere() };
+    let sky = HdrColor::from_color(Color::new(0, 0, 180));
+    let mut scene = Scene {
+        light_source: Point3::new(30.0, 30.0, 200.0),
+        background: Rc::new(Uniform { color: sky }),
+        spheres: vec![sphere],
+        // in the middle of the 120x60 panorama
+        eye: Point3::new(30.0, 60.0, 30.0),
+        camera: Camera { projection: Projection::Equirectangular, samples: 4, ..Camera::pinhole() },
+        ..make_scene()
+    };
+    // the sphere behind the eye is at both sides of the panorama, the floor is below and the sky above
+    assert_eq!(scene.sample_at(1, 30).object_id, raytracer::FLOOR_OBJECT_ID + 1);
+    assert_eq!(scene.sample_at(119, 30).object_id, raytracer::FLOOR_OBJECT_ID + 1);
+    assert_eq!(scene.sample_at(90, 30).object_id, 0);
+    let below = scene.sample_at(60, 5);
+    assert_eq!(below.object_id, raytracer::FLOOR_OBJECT_ID);
+    assert_close_vectors!(below.normal, Vec3::new(0.0, 0.0, 1.0), 1e-9);
+    assert_close_hdr_colors!(scene.sample_at(60, 55).radiance, sky, 1e-9);
+    // fisheye corners are outside of the picture
+    scene.camera.projection = Projection::Fisheye { field_of_view: f64::consts::PI };
+    let corner = scene.sample_at(0, 0);
+    assert_eq!(corner.object_id, 0);
+    assert_eq!(corner.radiance, HdrColor::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn moving_instances() {
+    let sphere: Rc<dyn Shape> = Rc::new(make_sphere());
+    let moving = MovingInstance::new(sphere, vec![
+        (0.0, Transform::translation(Vec3::new(-10.0, 0.0, 0.0))),
+        (1.0, Transform::translation(Vec3::new(-10.0, 10.0, 0.0))),
+        (2.0, Transform::translation(Vec3::new(-10.0, 10.0, 0.0)).then(&Transform::scaling(2.0, 2.0, 2.0).unwrap())),
+    ]);
+    assert_close_points!(moving.get_transform_at(0.5).apply_to_point(Point3::origin()), Point3::new(-10.0, 5.0, 0.0), 1e-9);
+    assert_close_points!(moving.get_transform_at(-1.0).apply_to_point(Point3::origin()), Point3::new(-10.0, 0.0, 0.0), 1e-9);
+    assert_close_points!(moving.get_transform_at(1.5).apply_to_point(Point3::origin()), Point3::new(-15.0, 15.0, 0.0), 1e-9);
+    assert_close_points!(moving.get_transform_at(5.0).apply_to_point(Point3::origin()), Point3::new(-20.0, 20.0, 0.0), 1e-9);
+    let ray = Ray::new(Point3::new(0.0, 5.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+    assert!(moving.get_colored_intersections(ray).is_empty());
+    let points = moving.get_colored_intersections(Ray { time: 0.5, ..ray });
+    let closest = raytracer::get_closest_point(ray.start, &points).unwrap();
+    assert_close_points!(closest.point, Point3::new(-9.0, 5.0, 0.0), 1e-9);
+}
+
+#[test]
+fn motion_blur() {
+    let sphere: Rc<dyn Shape> = Rc::new(Sphere { radius: 10.0, ..make_sphere() });
+    // goes from the left to the right of the view while the shutter is open
+    let moving = MovingInstance::new(sphere, vec![
+        (0.0, Transform::translation(Vec3::new(-100.0, 0.0, 30.0))),
+        (1.0, Transform::translation(Vec3::new(-100.0, 60.0, 30.0))),
+    ]);
+    let mut scene = Scene {
+        floor: None,
+        light_source: Point3::new(0.0, 30.0, 200.0),
+        objects: vec![Rc::new(moving)],
+        camera: Camera { samples: 64, ..Camera::pinhole() },
+        ..make_scene()
+    };
+    assert_eq!(scene.sample_at(30, 30).radiance, HdrColor::new(0.0, 0.0, 0.0));
+    let still = scene.sample_at(23, 30).radiance;
+    assert!(still.g > 0.0);
+    // a streak along the whole way, fainter than the sphere standing still
+    scene.camera.shutter_close = 1.0;
+    let streak = scene.sample_at(30, 30).radiance;
+    assert!(streak.g > 0.0 && streak.g < still.g * 0.5);
+    assert!(scene.sample_at(23, 30).radiance.g < still.g);
+    // an eye moving along with the sphere sees it sharp
+    scene.camera.eye_motion = Vec3::new(0.0, 60.0, 0.0);
+    assert_close_hdr_colors!(scene.sample_at(23, 30).radiance, still, 0.05);
+    assert_eq!(scene.sample_at(30, 30).radiance, HdrColor::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn keyframe_tracks() {
+    let empty: Track<f64> = Track::default();
+    assert_eq!(empty.value_at(1.0), None);
+    let track = Track::new(vec![
+        Keyframe::smooth(2.0, 10.0),
+        Keyframe::linear(0.0, 0.0),
+        Keyframe::step(3.0, 20.0),
+        Keyframe::linear(4.0, 30.0),
+    ]);
+    assert_eq!(track.get_keyframes()[0].interpolation, Interpolation::Linear);
+    assert_eq!(track.value_at(-1.0), Some(0.0));
+    assert!((track.value_at(0.5).unwrap() - 2.5).abs() < 1e-9);
+    assert!((track.value_at(2.5).unwrap() - 15.0).abs() < 1e-9);
+    // eases out of and into the keyframes
+    assert!((track.value_at(2.25).unwrap() - 11.5625).abs() < 1e-9);
+    assert!((track.value_at(2.75).unwrap() - 18.4375).abs() < 1e-9);
+    assert_eq!(track.value_at(3.9), Some(20.0));
+    assert_eq!(track.value_at(4.0), Some(30.0));
+    assert_eq!(track.value_at(10.0), Some(30.0));
+    let colors = Track::new(vec![Keyframe::linear(0.0, Color::new(0, 100, 255)), Keyframe::linear(1.0, Color::new(255, 0, 255))]);
+    assert_eq!(colors.value_at(0.5), Some(Color::new(128, 50, 255)));
+}
+
+#[test]
+fn scene_animation() {
+    let sphere = Sphere { center: Point3::new(-100.0, 30.0, 30.0), radius: 10.0, ..make_sphere() };
+    let mut scene = Scene {
+        floor: None,
+        light_source: Point3::new(0.0, 30.0, 200.0),
+        spheres: vec![sphere.clone(), sphere],
+        camera: Camera { shutter_close: 0.5, ..Camera::pinhole() },
+        ..make_scene()
+    };
+    let animation = SceneAnimation {
+        eye: Track::new(vec![Keyframe::linear(0.0, Point3::new(30.0, 30.0, 30.0)), Keyframe::linear(10.0, Point3::new(30.0, 50.0, 30.0))]),
+        exposure: Track::new(vec![Keyframe::linear(0.0, 0.0), Keyframe::linear(10.0, 1.0)]),
+        spheres: vec![SphereAnimation {
+            index: 1,
+            radius: Track::new(vec![Keyframe::smooth(0.0, 10.0), Keyframe::smooth(10.0, 20.0)]),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    animation.apply(&mut scene, 5.0).unwrap();
+    assert_close_points!(scene.eye, Point3::new(30.0, 40.0, 30.0), 1e-9);
+    assert!((scene.exposure - 0.5).abs() < 1e-9);
+    assert!((scene.spheres[1].radius - 15.0).abs() < 1e-9);
+    // untouched without a track
+    assert_eq!(scene.spheres[0].radius, 10.0);
+    assert_eq!(scene.light_source, Point3::new(0.0, 30.0, 200.0));
+    // the shutter opens at the frame and the eye blurs along its track
+    assert_eq!((scene.camera.shutter_open, scene.camera.shutter_close), (5.0, 5.5));
+    assert!((scene.camera.eye_motion.y - 2.0).abs() < 1e-9);
+    assert!(scene.camera.eye_motion.x.abs() < 1e-9);
+}
+
+#[test]
+fn animated_objects() {
+    let sphere: Rc<dyn Shape> = Rc::new(Sphere { radius: 10.0, ..make_sphere() });
+    let mut scene = Scene { objects: vec![sphere], ..make_scene() };
+    let animation = SceneAnimation {
+        objects: vec![ObjectAnimation {
+            index: 0,
+            transform: Track::new(vec![
+                Keyframe::linear(0.0, Transform::translation(Vec3::new(-100.0, 0.0, 10.0))),
+                Keyframe::linear(10.0, Transform::translation(Vec3::new(-100.0, 20.0, 10.0))),
+            ]),
+        }],
+        ..Default::default()
+    };
+    animation.apply(&mut scene, 5.0).unwrap();
+    let ray = Ray { time: 5.0, ..Ray::new(Point3::new(0.0, 10.0, 10.0), Vec3::new(-1.0, 0.0, 0.0)) };
+    let points = scene.objects[0].get_colored_intersections(ray);
+    let closest = raytracer::get_closest_point(ray.start, &points).unwrap();
+    assert_close_points!(closest.point, Point3::new(-90.0, 10.0, 10.0), 1e-9);
+    // an animation of something that isn't there leaves the scene alone
+    let eye = scene.eye;
+    let missing_sphere = SceneAnimation {
+        eye: Track::new(vec![Keyframe::linear(0.0, Point3::new(0.0, 0.0, 0.0))]),
+        spheres: vec![SphereAnimation { index: 2, ..Default::default() }],
+        ..Default::default()
+    };
+    assert_eq!(missing_sphere.apply(&mut scene, 0.0), Err(AnimationError::MissingSphere(2)));
+    assert_eq!(scene.eye, eye);
+    let missing_object = SceneAnimation { objects: vec![ObjectAnimation { index: 1, ..Default::default() }], ..Default::default() };
+    let error = missing_object.apply(&mut scene, 0.0).unwrap_err();
+    assert_eq!(error, AnimationError::MissingObject(1));
+    assert_eq!(error.to_string(), "animated object 1 isn't in the scene");
+}
+
+#[test]
+fn accumulation() {
+    let mut accumulation = Accumulation::new(2, 1, &[Aov::Depth]);
+    accumulation.add_sample(0, 0, HdrColor::new(1.0, 0.0, 0.0));
+    accumulation.add_sample(0, 0, HdrColor::new(0.0, 0.0, 1.0));
+    assert_eq!(accumulation.get_sample_count(0, 0), 2);
+    assert_eq!(accumulation.get_passes(), 0);
+    let framebuffer = accumulation.to_framebuffer();
+    assert_eq!(framebuffer.get_pixel(0, 0), HdrColor::new(0.5, 0.0, 0.5));
+    assert_eq!(framebuffer.get_pixel(1, 0), HdrColor::new(0.0, 0.0, 0.0));
+    assert!(framebuffer.get_layer("depth").is_some());
+    accumulation.add_sample(1, 0, HdrColor::new(0.0, 1.0, 0.0));
+    assert_eq!(accumulation.get_passes(), 1);
+}
+
+#[test]
+fn progressive_rendering() {
+    let with_camera = |camera: Camera| Scene {
+        floor: Some(Floor::new(16.0, BLACK, WHITE)),
+        light_source: Point3::new(-100.0, 20.0, 100.0),
+        background: Rc::new(Uniform { color: HdrColor::new(0.0, 0.0, 0.5) }),
+        spheres: vec![Sphere { center: Point3::new(-50.0, 20.0, 15.0), radius: 15.0, ..make_sphere() }],
+        eye: Point3::new(20.0, 20.0, 20.0),
+        camera,
+        ..make_scene()
+    };
+    // the first pass goes through the middle of the pixels like a render with one sample
+    let pinhole = with_camera(Camera::pinhole());
+    let mut accumulation = Accumulation::new(40, 40, &[Aov::ObjectId]);
+    pinhole.add_samples(&mut accumulation, 0..40);
+    let mut expected = Framebuffer::new(40, 40);
+    pinhole.render_with_aovs(&mut expected, &[Aov::ObjectId]);
+    let first_pass = accumulation.to_framebuffer();
+    assert_eq!(first_pass.get_pixels(), expected.get_pixels());
+    assert_eq!(first_pass.get_layer("object_id").unwrap().get_pixels(), expected.get_layer("object_id").unwrap().get_pixels());
+    let scene = with_camera(Camera::thin_lens(2.0, 70.0));
+    let mut accumulation = Accumulation::new(40, 40, &[]);
+    scene.add_samples(&mut accumulation, 0..40);
+    // interrupted passes leave the rest of the image behind
+    scene.add_samples(&mut accumulation, 0..10);
+    assert_eq!(accumulation.get_passes(), 1);
+    assert_eq!((accumulation.get_sample_count(5, 9), accumulation.get_sample_count(5, 10)), (2, 1));
+    for _ in 0..6 {
+        scene.add_samples(&mut accumulation, 0..40);
+    }
+    scene.add_samples(&mut accumulation, 10..40);
+    assert_eq!(accumulation.get_passes(), 8);
+    // the same samples as whole passes, just added in another order
+    let mut whole_passes = Accumulation::new(40, 40, &[]);
+    for _ in 0..8 {
+        scene.add_samples(&mut whole_passes, 0..40);
+    }
+    assert_eq!(accumulation.to_framebuffer().get_pixels(), whole_passes.to_framebuffer().get_pixels());
+}
+
+#[test]
+fn checkpoints() {
+    let scene = Scene {
+        floor: Some(Floor::new(16.0, BLACK, WHITE)),
+        light_source: Point3::new(-100.0, 20.0, 100.0),
+        background: Rc::new(Uniform { color: HdrColor::new(0.0, 0.0, 0.5) }),
+        eye: Point3::new(20.0, 20.0, 20.0),
+        camera: Camera { seed: 5, ..Camera::thin_lens(2.0, 70.0) },
+        ..make_scene()
+    };
+    let mut accumulation = Accumulation::new(30, 20, &[Aov::Depth]);
+    scene.add_samples(&mut accumulation, 0..20);
+    scene.add_samples(&mut accumulation, 0..7);
+    let mut data = vec![];
+    accumulation.write(&mut data, scene.camera.seed).unwrap();
+    let (mut resumed, seed) = Accumulation::read(&mut &data[..]).unwrap();
+    assert_eq!(seed, 5);
+    assert_eq!((resumed.get_width(), resumed.get_height()), (30, 20));
+    assert_eq!((resumed.get_sample_count(3, 6), resumed.get_sample_count(3, 7)), (2, 1));
+    assert_eq!(resumed.to_framebuffer().get_pixels(), accumulation.to_framebuffer().get_pixels());
+    assert_eq!(
+        resumed.to_framebuffer().get_layer("depth").unwrap().get_pixels(),
+        accumulation.to_framebuffer().get_layer("depth").unwrap().get_pixels(),
+    );
+    // continuing the resumed samples is the same as never stopping
+    scene.add_samples(&mut accumulation, 7..20);
+    scene.add_samples(&mut resumed, 7..20);
+    assert_eq!(resumed.to_framebuffer().get_pixels(), accumulation.to_framebuffer().get_pixels());
+    let path = env::temp_dir().join("raytracer_test_checkpoint.acc");
+    let path = path.to_str().unwrap();
+    resumed.save(path, seed).unwrap();
+    let (opened, _) = Accumulation::open(path).unwrap();
+    assert_eq!(opened.get_passes(), 2);
+    fs::remove_file(path).unwrap();
+    assert!(Accumulation::read(&mut &b"RTACCUM0"[..]).is_err());
+    assert!(Accumulation::read(&mut &data[..100]).is_err());
+}
+
+#[test]
+fn regions_and_tiles() {
+    let tiles: Vec<Region> = (1..4).map(|index| Region::tile(index, 3, 10, 20)).collect();
+    assert_eq!(tiles, vec![Region::new(0, 0, 10, 6), Region::new(0, 6, 10, 13), Region::new(0, 13, 10, 20)]);
+    let mut framebuffer = Framebuffer::new(4, 3);
+    framebuffer.add_layer("depth").set_pixel(2, 1, HdrColor::new(5.0, 5.0, 5.0));
+    framebuffer.set_pixel(2, 1, HdrColor::new(1.0, 0.0, 0.0));
+    let cropped = framebuffer.crop(Region::new(1, 1, 3, 3));
+    assert_eq!((cropped.get_width(), cropped.get_height()), (2, 2));
+    assert_eq!(cropped.get_pixel(1, 0), HdrColor::new(1.0, 0.0, 0.0));
+    assert_eq!(cropped.get_layer("depth").unwrap().get_pixel(1, 0), HdrColor::new(5.0, 5.0, 5.0));
+    let mut pasted = Framebuffer::new(4, 3);
+    pasted.paste(&cropped, 1, 1);
+    assert_eq!(pasted.get_pixels(), framebuffer.get_pixels());
+}
+
+#[test]
+fn region_rendering() {
+    let scene = Scene {
+        floor: Some(Floor::new(16.0, BLACK, WHITE)),
+        light_source: Point3::new(-100.0, 20.0, 100.0),
+        background: Rc::new(Uniform { color: HdrColor::new(0.0, 0.0, 0.5) }),
+        eye: Point3::new(20.0, 20.0, 20.0),
+        ..make_scene()
+    };
+    let mut full = Framebuffer::new(40, 40);
+    scene.render_with_aovs(&mut full, &[Aov::Depth]);
+    // the same pixels inside the region and black around it
+    let region = Region::new(5, 10, 25, 30);
+    let mut partial = Framebuffer::new(40, 40);
+    scene.render_region_with_aovs(&mut partial, &[Aov::Depth], region);
+    assert_eq!(partial.crop(region).get_pixels(), full.crop(region).get_pixels());
+    assert_eq!(
+        partial.crop(region).get_layer("depth").unwrap().get_pixels(),
+        full.crop(region).get_layer("depth").unwrap().get_pixels(),
+    );
+    assert_eq!(partial.get_pixel(4, 10), HdrColor::new(0.0, 0.0, 0.0));
+    assert_eq!(partial.get_pixel(5, 30), HdrColor::new(0.0, 0.0, 0.0));
+    // bands merged from the top are the whole image
+    let mut merged = Framebuffer::new(40, 40);
+    for index in 1..4 {
+        let tile = Region::tile(index, 3, 40, 40);
+        let mut framebuffer = Framebuffer::new(40, 40);
+        scene.render_region_with_aovs(&mut framebuffer, &[], tile);
+        merged.paste(&framebuffer.crop(tile), 0, tile.top);
+    }
+    assert_eq!(merged.get_pixels(), full.get_pixels());
+    let mut accumulation = Accumulation::new(40, 40, &[]);
+    scene.add_samples_in_region(&mut accumulation, region);
+    assert_eq!((accumulation.get_passes_in(region), accumulation.get_passes()), (1, 0));
+    assert_eq!(accumulation.to_framebuffer().crop(region).get_pixels(), full.crop(region).get_pixels());
+}
+
+#[test]
+fn exr_tiles() {
+    let scene = Scene {
+        floor: Some(Floor::new(16.0, BLACK, WHITE)),
+        light_source: Point3::new(-100.0, 20.0, 100.0),
+        background: Rc::new(Uniform { color: HdrColor::new(0.0, 0.0, 0.5) }),
+        eye: Point3::new(20.0, 20.0, 20.0),
+        ..make_scene()
+    };
+    let mut full = Framebuffer::new(40, 40);
+    scene.render_with_aovs(&mut full, &[Aov::Depth]);
+    // bands saved as exr and loaded again keep their aov layers
+    let mut merged = Framebuffer::new(40, 40);
+    merged.add_layer("depth");
+    for index in 1..3 {
+        let tile = Region::tile(index, 2, 40, 40);
+        let mut framebuffer = Framebuffer::new(40, 40);
+        scene.render_region_with_aovs(&mut framebuffer, &[Aov::Depth], tile);
+        let path = env::temp_dir().join(format!("raytracer_test_tile.{}.exr", index));
+        let path = path.to_str().unwrap();
+        let format = ImageFormat::Exr(ExrPrecision::Float);
+        output::save_framebuffer(path, format, &framebuffer.crop(tile), ToneMapping::Clamp, 0.0).unwrap();
+        let loaded = input::load_image(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!((loaded.get_width(), loaded.get_height()), (40, tile.get_height()));
+        merged.paste(&loaded, 0, tile.top);
+    }
+    let to_float = |pixels: &[HdrColor]| -> Vec<HdrColor> {
+        pixels.iter().map(|p| HdrColor::new(p.r as f32 as f64, p.g as f32 as f64, p.b as f32 as f64)).collect()
+    };
+    assert_eq!(merged.get_pixels().to_vec(), to_float(full.get_pixels()));
+    assert_eq!(merged.get_layer("depth").unwrap().get_pixels().to_vec(), to_float(full.get_layer("depth").unwrap().get_pixels()));
+    // halves that are exact come back the same
+    let pixels = vec![HdrColor::new(0.5, -2.0, 65504.0), HdrColor::new(f64::INFINITY, 0.0, 1.0 / 1024.0)];
+    let path = env::temp_dir().join("raytracer_test_half.exr");
+    let path = path.to_str().unwrap();
+    output::save_hdr_image(path, ImageFormat::Exr(ExrPrecision::Half), 2, 1, &pixels).unwrap();
+    let loaded = input::load_image(path).unwrap();
+    fs::remove_file(path).unwrap();
+    assert_eq!(loaded.get_pixels().to_vec(), pixels);
+    assert!(loaded.get_layers().is_empty());
+}
+
+fn distance_between_hdr_colors(first: HdrColor, second: HdrColor) -> f64 {
+    ((first.r - second.r).powi(2) + (first.g - second.g).powi(2) + (first.b - second.b).powi(2)).sqrt()
 }
 
 fn distance_between_colors(first: Color, second: Color) -> f64 {
@@ -215,3 +1670,28 @@ fn distance_between_colors(first: Color, second: Color) -> f64 {
             (first.b as i32 - second.b as i32).pow(2)) as f64;
     sum_squares.sqrt()
 }
+
+fn make_scene() -> Scene {
+    Scene {
+        floor: Some(Floor::new(32.0, BLACK, WHITE)),
+        light_source: Point3::new(-200.0, 10.0, 200.0),
+        background: Rc::new(Uniform { color: HdrColor::new(0.0, 0.0, 0.0) }),
+        spheres: vec![],
+        objects: vec![],
+        eye: Point3::new(30.0, 30.0, 30.0),
+        camera: Camera::pinhole(),
+        tone_mapping: ToneMapping::Clamp,
+        exposure: 0.0,
+    }
+}
+
+fn make_sphere() -> Sphere {
+    Sphere {
+        center: Point3::new(0.0, 0.0, 0.0),
+        radius: 1.0,
+        color: Color::new(0, 150, 0),
+        is_mirror: false,
+        texture: None,
+        bump: None,
+    }
+}